@@ -1,8 +1,363 @@
-use core::CargoRunner;
-use std::path::PathBuf;
-fn main() {
-    CargoRunner::init();
-    let config_path  = PathBuf::from("cargo-runner-leptos.toml");
-    let config = CargoRunner::load(config_path);
-    println!("{:#?}", config);
+use clap::{Parser, Subcommand};
+use core::{
+    CargoRunner, ConfigDocument, Context, Diagnostic, ParseMode, PresetCache, RetentionPolicy,
+    Variables,
+};
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
+
+#[derive(Parser)]
+#[command(name = "rx", version, about = "Run, build, test and bench rust code")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate the default config, `config.toml` in the cargo-runner home
+    ///
+    /// The cargo-runner home is the first of `$CARGO_RUNNER_HOME`,
+    /// `$XDG_CONFIG_HOME/cargo-runner` unless only `~/.cargo-runner` exists, and
+    /// `~/.cargo-runner`.
+    Init,
+    /// Back up the default config and replace it with the built-in one
+    Reset,
+    /// List, compare, restore and prune the backups of a config file
+    ///
+    /// `rx reset`, `rx migrate`, `rx update` and `rx backup restore` back the config up
+    /// to `config.$number.bak` before changing it. Backups are never removed on their
+    /// own, run `rx backup prune` to drop old ones.
+    Backup {
+        #[command(subcommand)]
+        command: BackupCommand,
+        /// Config whose backups to manage, defaults to `config.toml` in the
+        /// cargo-runner home, see `rx init --help`
+        #[arg(long, global = true)]
+        path: Option<PathBuf>,
+    },
+    /// Fetch the presets downloaded into a config again and merge the ones that changed
+    Update {
+        /// Config whose presets to update, defaults to `config.toml` in the
+        /// cargo-runner home, see `rx init --help`
+        #[arg(long)]
+        path: Option<PathBuf>,
+        /// Only use presets cached by earlier downloads, without going online
+        #[arg(long)]
+        offline: bool,
+    },
+    /// Show or change the default config of a context
+    Default {
+        /// `run`, `build`, `test`, `bench` or a custom context such as `doc`
+        #[arg(value_parser = str::parse::<Context>)]
+        context: Context,
+        /// Config to use as the new default
+        name: Option<String>,
+        /// Config file to edit, defaults to `config.toml` in the
+        /// cargo-runner home, see `rx init --help`
+        #[arg(long)]
+        path: Option<PathBuf>,
+    },
+    /// Validate a config file and report every problem found in it
+    Validate {
+        /// Config to validate, defaults to `cargo-runner.toml` in the current directory
+        path: Option<PathBuf>,
+        /// Validate the default config instead
+        #[arg(long, conflicts_with = "path")]
+        default: bool,
+        /// Report values that would fall back to a default, such as an unknown `command_type`, as warnings
+        #[arg(long)]
+        lenient: bool,
+    },
+    /// Upgrade a config file written in an older layout, backing it up first
+    Migrate {
+        /// Config to migrate, defaults to `cargo-runner.toml` in the current directory
+        path: Option<PathBuf>,
+        /// Migrate the default config instead
+        #[arg(long, conflicts_with = "path")]
+        default: bool,
+    },
+    /// Print the JSON Schema of config files, for editor completion and validation
+    Schema {
+        /// Write the schema to a file instead of printing it
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Print the command a config runs, with every `${..}` placeholder expanded
+    Plan {
+        /// `run`, `build`, `test`, `bench` or a custom context such as `doc`
+        #[arg(value_parser = str::parse::<Context>)]
+        context: Context,
+        /// Config to plan, defaults to the context's default
+        name: Option<String>,
+        /// Source file or directory to plan the command for, defaults to the current directory
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Replace the config's `sub_command`, which must be one of its `allowed_subcommands`
+        #[arg(long)]
+        sub_command: Option<String>,
+    },
+    /// Show which config file set each setting of a config, and what it shadowed
+    Explain {
+        /// `run`, `build`, `test`, `bench` or a custom context such as `doc`
+        #[arg(value_parser = str::parse::<Context>)]
+        context: Context,
+        /// Config to explain, defaults to the context's default
+        name: Option<String>,
+        /// Source file or directory to resolve the config for, defaults to the current directory
+        #[arg(long)]
+        file: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum BackupCommand {
+    /// List the backups, oldest first
+    List,
+    /// Show how a backup differs from the current file
+    Diff {
+        /// Backup to compare, defaults to the latest one
+        index: Option<u32>,
+    },
+    /// Replace the config with a backup, backing up the current file first
+    Restore {
+        /// Backup to restore, defaults to the latest one
+        index: Option<u32>,
+    },
+    /// Remove old backups, keeping the last 10 unless a limit is given
+    Prune {
+        /// Number of latest backups to keep
+        #[arg(long)]
+        keep: Option<usize>,
+        /// Remove backups older than this many days
+        #[arg(long)]
+        max_age_days: Option<u64>,
+    },
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<ExitCode, core::Error> {
+    match cli.command {
+        Command::Init => {
+            CargoRunner::init()?;
+            println!(
+                "Config ready at: {}",
+                CargoRunner::get_default_config_path()?.display()
+            );
+        }
+        Command::Reset => {
+            if let Some(backup) = CargoRunner::reset()? {
+                println!("Backup created at: {}", backup.display());
+            }
+        }
+        Command::Backup { command, path } => {
+            let path = match path {
+                Some(path) => path,
+                None => CargoRunner::get_default_config_path()?,
+            };
+            backup(&path, command)?;
+        }
+        Command::Update { path, offline } => {
+            let path = match path {
+                Some(path) => path,
+                None => CargoRunner::get_default_config_path()?,
+            };
+            update(&path, offline)?;
+        }
+        Command::Default {
+            context,
+            name,
+            path,
+        } => {
+            let path = match path {
+                Some(path) => path,
+                None => CargoRunner::get_default_config_path()?,
+            };
+
+            match name {
+                Some(name) => {
+                    let mut document = ConfigDocument::load(&path)?;
+                    document.set_default(context.clone(), &name)?;
+                    document.save()?;
+                    println!("{} default set to: {}", context, name);
+                }
+                None => {
+                    let config = CargoRunner::load(path)?;
+                    println!("{}", config.get_default(context).unwrap_or_default());
+                }
+            }
+        }
+        Command::Validate {
+            path,
+            default,
+            lenient,
+        } => {
+            let path = match (path, default) {
+                (_, true) => CargoRunner::get_default_config_path()?,
+                (Some(path), _) => path,
+                (None, _) => PathBuf::from("cargo-runner.toml"),
+            };
+            let mode = if lenient {
+                ParseMode::Lenient
+            } else {
+                ParseMode::Strict
+            };
+            let diagnostics = CargoRunner::validate_with(&path, mode)?;
+            return Ok(report(&path, &diagnostics));
+        }
+        Command::Migrate { path, default } => {
+            let path = match (path, default) {
+                (_, true) => CargoRunner::get_default_config_path()?,
+                (Some(path), _) => path,
+                (None, _) => PathBuf::from("cargo-runner.toml"),
+            };
+            println!("{}: {}", path.display(), CargoRunner::migrate_file(&path)?);
+        }
+        Command::Schema { output } => {
+            let schema = format!("{:#}\n", CargoRunner::json_schema());
+            match output {
+                Some(path) => std::fs::write(&path, schema)
+                    .map_err(|e| core::Error::Io {
+                        action: "failed to write",
+                        path,
+                        source: e,
+                    })?,
+                None => print!("{}", schema),
+            }
+        }
+        Command::Plan {
+            context,
+            name,
+            file,
+            sub_command,
+        } => {
+            let file = file.unwrap_or_else(|| PathBuf::from("."));
+            let mut resolved = CargoRunner::resolve(&file)?;
+            if let Some(sub_command) = sub_command {
+                resolved.set_sub_command(context.clone(), name.as_deref(), &sub_command)?;
+            }
+            let variables = Variables::for_file(&file)?;
+            println!("{}", resolved.runner.plan(context, name.as_deref(), &variables)?);
+        }
+        Command::Explain {
+            context,
+            name,
+            file,
+        } => {
+            let file = file.unwrap_or_else(|| PathBuf::from("."));
+            let resolved = CargoRunner::resolve(&file)?;
+            match resolved.explain(context.clone(), name.as_deref()) {
+                Some(explanation) => print!("{}", explanation),
+                None => {
+                    return Err(match name {
+                        Some(name) => core::Error::UnknownConfig {
+                            context: context.into(),
+                            name,
+                        },
+                        None => core::Error::MissingDefault(context.into()),
+                    })
+                }
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn backup(path: &Path, command: BackupCommand) -> Result<(), core::Error> {
+    match command {
+        BackupCommand::List => {
+            let backups = CargoRunner::list_backups(path)?;
+            if backups.is_empty() {
+                println!("{}: no backups", path.display());
+            }
+            for backup in backups {
+                println!("{}", backup);
+            }
+        }
+        BackupCommand::Diff { index } => {
+            let backup = CargoRunner::find_backup(path, index)?;
+            println!("--- {}", backup.path.display());
+            println!("+++ {}", path.display());
+            for line in CargoRunner::diff_backup(path, &backup)? {
+                println!("{}", line);
+            }
+        }
+        BackupCommand::Restore { index } => {
+            let backup = CargoRunner::find_backup(path, index)?;
+            if let Some(current) = CargoRunner::restore_backup(path, &backup)? {
+                println!("Backup created at: {}", current.display());
+            }
+            println!("{} restored from: {}", path.display(), backup.path.display());
+        }
+        BackupCommand::Prune { keep, max_age_days } => {
+            let policy = if keep.is_none() && max_age_days.is_none() {
+                RetentionPolicy::default()
+            } else {
+                RetentionPolicy {
+                    keep_last: keep,
+                    max_age: max_age_days.map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+                }
+            };
+            let removed = CargoRunner::prune_backups(path, &policy)?;
+            if removed.is_empty() {
+                println!("{}: no backups to remove", path.display());
+            }
+            for backup in removed {
+                println!("removed {}", backup.path.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn update(path: &Path, offline: bool) -> Result<(), core::Error> {
+    let report = if offline {
+        CargoRunner::update_with_fetcher(path, &PresetCache::from_env()?)?
+    } else {
+        CargoRunner::update_blocking(path)?
+    };
+    if report.presets.is_empty() {
+        println!("{}: no presets to update", path.display());
+    }
+    for preset in &report.presets {
+        print!("{}", preset);
+    }
+
+    if !report.diff.is_empty() {
+        println!("--- {}", path.display());
+        println!("+++ {}", path.display());
+        for line in &report.diff {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+fn report(path: &Path, diagnostics: &[Diagnostic]) -> ExitCode {
+    for diagnostic in diagnostics {
+        // Diagnostics with a span already start with `line:column: `
+        let separator = if diagnostic.span.is_some() { ":" } else { ": " };
+        println!("{}{}{}", path.display(), separator, diagnostic);
+    }
+
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        ExitCode::FAILURE
+    } else {
+        if diagnostics.is_empty() {
+            println!("{}: ok", path.display());
+        }
+        ExitCode::SUCCESS
+    }
 }
@@ -0,0 +1,30 @@
+use core::{CargoRunner, RetentionPolicy};
+use std::time::Duration;
+
+/// Use when you want to go back to a config from before a reset, download or migration
+/// Every backup is a `config.$number.bak` file next to the config, the highest number
+/// is the latest one, and backups are only removed by `prune_backups`
+fn main() -> Result<(), core::Error> {
+    let path = CargoRunner::get_default_config_path()?;
+
+    for backup in CargoRunner::list_backups(&path)? {
+        println!("{}", backup);
+    }
+
+    // Show what restoring the latest backup would change, then restore it
+    let latest = CargoRunner::find_backup(&path, None)?;
+    for line in CargoRunner::diff_backup(&path, &latest)? {
+        println!("{}", line);
+    }
+    CargoRunner::restore_backup(&path, &latest)?;
+
+    // Keep the last 3 backups, and none older than a week
+    let policy = RetentionPolicy {
+        keep_last: Some(3),
+        max_age: Some(Duration::from_secs(7 * 24 * 60 * 60)),
+    };
+    for backup in CargoRunner::prune_backups(&path, &policy)? {
+        println!("removed {}", backup.path.display());
+    }
+    Ok(())
+}
@@ -6,11 +6,16 @@ use std::path::PathBuf;
 /// And would merge the downloaded config with the default config
 /// If a save_path is provided it would save the config to the specified path
 /// If the save_path already exists it would merge the downloaded config with the existing config
+/// Sources can also be `file://` URLs, plain paths or `git+file:///repo#path/to/preset.toml@rev`,
+/// pinned with `#sha256=<hex>` (`&sha256=<hex>` for git) to reject content that changed
+/// Needs the `async` feature, `CargoRunner::download_blocking` works without a runtime
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> Result<(), core::Error> {
     let url = "https://gist.githubusercontent.com/codeitlikemiley/26205a6d642c33dbdcf9fc85b79f29bf/raw/a59d51136aca2fed51ca45de6b2319039e977637/leptos.toml";
     // CargoRunner::download(url,None).await?;
-    CargoRunner::download(url, Some(PathBuf::from("example-downloaded.toml"))).await?;
+    let downloaded =
+        CargoRunner::download(url, Some(PathBuf::from("example-downloaded.toml"))).await?;
+    println!("downloaded {} (sha256 {})", downloaded.source, downloaded.sha256);
 
     Ok(())
-}
\ No newline at end of file
+}
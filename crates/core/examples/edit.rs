@@ -0,0 +1,14 @@
+use core::{ConfigDocument, Context};
+use std::path::Path;
+
+/// Use when you want to change a config file without losing its comments, formatting or ordering
+/// Only the entries that change are rewritten, everything else is kept as the user wrote it
+fn main() -> Result<(), core::Error> {
+    let mut document = ConfigDocument::load(Path::new("example-leptos.toml"))?;
+
+    document.set_default(Context::Run, "leptos")?;
+    document.set_env(Context::Run, "leptos", "LEPTOS_ENV", "DEV")?;
+
+    println!("{}", document);
+    Ok(())
+}
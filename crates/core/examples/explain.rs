@@ -0,0 +1,14 @@
+use core::{CargoRunner, Context};
+use std::path::Path;
+
+/// Use when you want to know why a setting has the value it has
+/// Every default, config field and env key records the file that set it last
+/// and the values it shadowed
+fn main() -> Result<(), core::Error> {
+    let resolved = CargoRunner::resolve(Path::new("crates/core/src/lib.rs"))?;
+
+    if let Some(explanation) = resolved.explain(Context::Run, None) {
+        print!("{}", explanation);
+    }
+    Ok(())
+}
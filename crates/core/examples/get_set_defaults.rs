@@ -1,12 +1,12 @@
 use core::{CargoRunner, Context};
 use std::path::PathBuf;
 
-fn main() {
+fn main() -> Result<(), core::Error> {
     let path = PathBuf::from("cargo-runner-leptos.toml");
 
-    let mut config = CargoRunner::load(path);
+    let mut config = CargoRunner::load(path)?;
 
-    config.merge(CargoRunner::default());
+    config.merge(CargoRunner::default())?;
 
     let default = config.get_default(Context::Run);
 
@@ -15,7 +15,7 @@ fn main() {
         default.unwrap_or_default()
     );
 
-    config.set_default(Context::Run, "leptos").unwrap();
+    config.set_default(Context::Run, "leptos")?;
 
     let default = config.get_default(Context::Run);
 
@@ -23,4 +23,5 @@ fn main() {
         "latest default for run context: {:#?}",
         default.unwrap_or_default()
     );
+    Ok(())
 }
@@ -1,7 +1,14 @@
-use core::CargoRunner;
+use core::{CargoRunner, ConfigPaths};
 
 /// Use when you want to initialize a new config at `~/.cargo-runner/config.toml`
-fn main() {
-    let config = CargoRunner::init();
+/// The directory is `$CARGO_RUNNER_HOME` or `$XDG_CONFIG_HOME/cargo-runner` when set
+fn main() -> Result<(), core::Error> {
+    let config = CargoRunner::init()?;
     println!("{:#?}", config);
+
+    // Or keep the config somewhere else, e.g. a temporary directory in tests
+    let paths = ConfigPaths::new(std::env::temp_dir().join("cargo-runner"));
+    CargoRunner::init_with(&paths)?;
+    println!("Config ready at: {}", paths.config_file().display());
+    Ok(())
 }
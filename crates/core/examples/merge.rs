@@ -2,14 +2,14 @@ use core::{CargoRunner, Context};
 use std::path::PathBuf;
 
 /// Use when you want to merge a specific config and override it with another config
-fn main() {
+fn main() -> Result<(), core::Error> {
     let mut config = CargoRunner::default();
 
     let path = PathBuf::from("cargo-runner-leptos.toml");
 
-    let leptos_config = CargoRunner::load(path);
+    let leptos_config = CargoRunner::load(path)?;
 
-    config.merge(leptos_config);
+    config.merge(leptos_config)?;
 
     let default = config.get_default(Context::Run);
 
@@ -19,4 +19,5 @@ fn main() {
     );
 
     println!("{:#?}", config);
+    Ok(())
 }
@@ -0,0 +1,20 @@
+use core::{CargoRunner, MergeOptions, MergeStrategy};
+use std::path::PathBuf;
+
+/// Use when a merged config should only fill in what the base config leaves out
+fn main() -> Result<(), core::Error> {
+    let mut config = CargoRunner::load(PathBuf::from("cargo-runner.toml"))?;
+
+    let leptos_config = CargoRunner::load(PathBuf::from("cargo-runner-leptos.toml"))?;
+
+    // Keep every field the base sets, but replace `env` and fail if the
+    // two files disagree on a `sub_command`
+    let options = MergeOptions::new(MergeStrategy::KeepBase)
+        .field("env", MergeStrategy::Override)
+        .field("sub_command", MergeStrategy::ErrorOnConflict);
+
+    let report = config.merge_with(leptos_config, &options)?;
+
+    println!("{}", report);
+    Ok(())
+}
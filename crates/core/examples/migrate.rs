@@ -0,0 +1,12 @@
+use core::CargoRunner;
+use std::path::Path;
+
+/// Use when you have a config written in an older layout, e.g. `[[run.commands]]`
+/// The file is backed up and rewritten, and the report lists every change made
+/// `CargoRunner::load` already reads older layouts, it just never rewrites the file
+fn main() -> Result<(), core::Error> {
+    let report = CargoRunner::migrate_file(Path::new("example-override.toml"))?;
+
+    println!("{}", report);
+    Ok(())
+}
@@ -0,0 +1,16 @@
+use core::{CargoRunner, Context, Variables};
+use std::path::Path;
+
+/// Use when you want the command a config runs for a source file
+/// `${..}` placeholders in the config values are expanded,
+/// e.g. `${env:HOME}`, `${workspaceRoot}`, `${packageRoot}`, `${file}`,
+/// `${package}`, `${bin}` or `${config:other.field}`, use `$$` for a literal `$`
+fn main() -> Result<(), core::Error> {
+    let file = Path::new("crates/cli/src/main.rs");
+    let resolved = CargoRunner::resolve(file)?;
+    let variables = Variables::for_file(file)?;
+
+    let plan = resolved.runner.plan(Context::Run, None, &variables)?;
+    println!("{}", plan);
+    Ok(())
+}
@@ -7,15 +7,16 @@ use std::path::PathBuf;
 /// e.g. when you want to pluck only the **leptos** config and remove other configs.
 /// prior merging to other configs.
 /// It also set all  default for any context that matches the **config_name**
-fn main() {
+fn main() -> Result<(), core::Error> {
     let mut config = CargoRunner::default();
     let path = PathBuf::from("example-leptos.toml");
-    let  leptos = CargoRunner::load(path);
+    let leptos = CargoRunner::load(path)?;
     {
-        config.merge(leptos);
+        config.merge(leptos)?;
     }
 
     let default = config.pluck("leptos");
 
     println!("{:#?}", default);
+    Ok(())
 }
@@ -0,0 +1,20 @@
+use core::{CargoRunner, Context};
+use std::path::PathBuf;
+
+/// The inverse of merging and plucking: removes configs by name, renames them
+/// and drops whole contexts, printing every change that was made.
+/// A config that is still the default of its context is never removed.
+fn main() -> Result<(), core::Error> {
+    let mut config = CargoRunner::default();
+    let path = PathBuf::from("example-leptos.toml");
+    config.merge(CargoRunner::load(path)?)?;
+
+    let mut changes = config.rename_config(Context::Run, "leptos", "watch")?;
+    changes.extend(config.remove_everywhere("leptos")?);
+    changes.extend(config.remove_context(Context::Bench)?);
+
+    for change in changes {
+        println!("{}", change);
+    }
+    Ok(())
+}
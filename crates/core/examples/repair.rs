@@ -0,0 +1,18 @@
+use core::{CargoRunner, LoadOutcome, RecoveryPolicy};
+use std::path::PathBuf;
+
+/// Use when you want to load a config that might be invalid and decide how to recover.
+/// `RecoveryPolicy::Error` returns the error and leaves the file alone,
+/// `RecoveryPolicy::BackupAndReset` backs up the file to `$name.$number.bak` and writes the default config,
+/// `RecoveryPolicy::KeepDefault` uses the default config without writing anything.
+fn main() -> Result<(), core::Error> {
+    let path = PathBuf::from("example-override.toml");
+    let (config, outcome) = CargoRunner::load_or_repair(path, RecoveryPolicy::KeepDefault)?;
+
+    if let LoadOutcome::Defaulted { error } = &outcome {
+        eprintln!("using the default config: {}", error);
+    }
+
+    println!("{:#?}", config);
+    Ok(())
+}
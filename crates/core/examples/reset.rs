@@ -4,6 +4,9 @@ use core::CargoRunner;
 /// This would backup the current default config
 /// to a filename with format `config.$number.bak` 
 /// Then replace the old config with the default config
-fn main() {
-    CargoRunner::reset();
+fn main() -> Result<(), core::Error> {
+    if let Some(backup) = CargoRunner::reset()? {
+        println!("Backup created at: {}", backup.display());
+    }
+    Ok(())
 }
\ No newline at end of file
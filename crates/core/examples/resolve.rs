@@ -0,0 +1,17 @@
+use core::CargoRunner;
+use std::path::Path;
+
+/// Use when you want the effective config for a source file
+/// It merges the built-in configs, `~/.cargo-runner/config.toml`,
+/// the workspace `cargo-runner.toml`, the package `cargo-runner.toml`
+/// and the nearest `cargo-runner.toml` to the file, in that order
+fn main() -> Result<(), core::Error> {
+    let resolved = CargoRunner::resolve(Path::new("crates/core/src/lib.rs"))?;
+
+    for file in resolved.files() {
+        println!("merged: {}", file.display());
+    }
+
+    println!("{:#?}", resolved.runner);
+    Ok(())
+}
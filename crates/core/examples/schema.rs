@@ -0,0 +1,10 @@
+use core::CargoRunner;
+
+/// Use when you want editor completion and validation for `cargo-runner.toml`
+/// The schema is generated from the config types, so it never goes out of date
+fn main() -> Result<(), core::Error> {
+    let schema = CargoRunner::json_schema();
+
+    println!("{:#}", schema);
+    Ok(())
+}
@@ -0,0 +1,14 @@
+use core::CargoRunner;
+use std::path::PathBuf;
+
+/// Use when you want to check a config for problems without loading it
+/// Every problem comes with a severity, a line and column span and an optional suggested fix
+fn main() -> Result<(), core::Error> {
+    let path = PathBuf::from("example-override.toml");
+    let diagnostics = CargoRunner::validate(&path)?;
+
+    for diagnostic in diagnostics {
+        println!("{}:{}", path.display(), diagnostic);
+    }
+    Ok(())
+}
@@ -0,0 +1,371 @@
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    writer::{self, FileLock, FileVersion},
+    CargoRunner, Error, Result,
+};
+
+/// A copy of a config file taken before it was overwritten, e.g. `config.3.bak`
+/// next to `config.toml`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+    pub path: PathBuf,
+    /// Backups are numbered from 0, the highest index is the latest one.
+    pub index: u32,
+    /// When the backup was taken.
+    pub modified: SystemTime,
+    /// Size in bytes.
+    pub size: u64,
+}
+
+impl Backup {
+    /// How long ago the backup was taken, zero if its time is in the future.
+    pub fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.modified)
+            .unwrap_or_default()
+    }
+}
+
+impl Display for Backup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:>3}  {}  {} bytes  {}",
+            self.index,
+            self.path.display(),
+            self.size,
+            DisplayAge(self.age())
+        )
+    }
+}
+
+/// Which backups [`CargoRunner::prune_backups`] keeps. A backup is removed when
+/// either limit is exceeded; `None` disables a limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// How many of the latest backups are kept.
+    pub keep_last: Option<usize>,
+    /// Backups older than this are removed.
+    pub max_age: Option<Duration>,
+}
+
+impl RetentionPolicy {
+    /// A policy that keeps every backup.
+    pub fn keep_all() -> Self {
+        RetentionPolicy {
+            keep_last: None,
+            max_age: None,
+        }
+    }
+}
+
+impl Default for RetentionPolicy {
+    /// Keeps the last 10 backups, what `rx backup prune` uses without limits.
+    fn default() -> Self {
+        RetentionPolicy {
+            keep_last: Some(10),
+            max_age: None,
+        }
+    }
+}
+
+/// One line of [`CargoRunner::diff_backup`], comparing a backup to the current file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+    Same(String),
+    /// Only in the backup, restoring it brings the line back.
+    Removed(String),
+    /// Only in the current file, restoring the backup drops the line.
+    Added(String),
+}
+
+impl Display for DiffLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiffLine::Same(line) => write!(f, " {}", line),
+            DiffLine::Removed(line) => write!(f, "-{}", line),
+            DiffLine::Added(line) => write!(f, "+{}", line),
+        }
+    }
+}
+
+impl CargoRunner {
+    /// Lists the backups of `config_path`, oldest first.
+    pub fn list_backups(config_path: &Path) -> Result<Vec<Backup>> {
+        let dir = backup_dir(config_path);
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let stem = backup_stem(config_path);
+
+        let mut backups = Vec::new();
+        let entries = fs::read_dir(&dir).map_err(|e| Error::io("failed to read", &dir, e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| Error::io("failed to read", &dir, e))?;
+            let Some(index) = backup_index(&entry.path(), &stem) else {
+                continue;
+            };
+            // Keep paths relative when `config_path` is
+            let path = config_path.with_file_name(entry.file_name());
+            let metadata =
+                fs::metadata(&path).map_err(|e| Error::io("failed to read", &path, e))?;
+            backups.push(Backup {
+                index,
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                size: metadata.len(),
+                path,
+            });
+        }
+        backups.sort_by_key(|backup| backup.index);
+        Ok(backups)
+    }
+
+    /// Finds the backup of `config_path` numbered `index`, or the latest one.
+    pub fn find_backup(config_path: &Path, index: Option<u32>) -> Result<Backup> {
+        let backups = Self::list_backups(config_path)?;
+        let backup = match index {
+            Some(index) => backups.into_iter().find(|backup| backup.index == index),
+            None => backups.into_iter().last(),
+        };
+        backup.ok_or_else(|| Error::UnknownBackup {
+            path: config_path.to_path_buf(),
+            index,
+        })
+    }
+
+    /// Compares `backup` to the current `config_path` line by line, a missing
+    /// file counting as empty.
+    pub fn diff_backup(config_path: &Path, backup: &Backup) -> Result<Vec<DiffLine>> {
+        let old = fs::read_to_string(&backup.path)
+            .map_err(|e| Error::io("failed to read", &backup.path, e))?;
+        let new = if config_path.exists() {
+            fs::read_to_string(config_path)
+                .map_err(|e| Error::io("failed to read", config_path, e))?
+        } else {
+            String::new()
+        };
+        Ok(diff_lines(&old, &new))
+    }
+
+    /// Replaces `config_path` with `backup`, backing up the current file first.
+    ///
+    /// Returns the path of the backup taken of the current file, if there was one.
+    /// Fails with [`Error::Modified`] if the file is written by anyone else while
+    /// it is backed up, instead of losing changes the backup does not have.
+    pub fn restore_backup(config_path: &Path, backup: &Backup) -> Result<Option<PathBuf>> {
+        let content =
+            fs::read(&backup.path).map_err(|e| Error::io("failed to read", &backup.path, e))?;
+        let version = FileVersion::current(config_path)?;
+        let current = Self::create_backup(config_path)?;
+        writer::write(config_path, &content, Some(version))?;
+        Ok(current)
+    }
+
+    /// Removes the backups of `config_path` that `policy` does not keep,
+    /// returning them oldest first.
+    pub fn prune_backups(config_path: &Path, policy: &RetentionPolicy) -> Result<Vec<Backup>> {
+        let backups = Self::list_backups(config_path)?;
+        let keep_from = match policy.keep_last {
+            Some(keep_last) => backups.len().saturating_sub(keep_last),
+            None => 0,
+        };
+
+        let mut removed = Vec::new();
+        for (position, backup) in backups.into_iter().enumerate() {
+            let too_old = policy.max_age.is_some_and(|max_age| backup.age() > max_age);
+            if position < keep_from || too_old {
+                fs::remove_file(&backup.path)
+                    .map_err(|e| Error::io("failed to remove", &backup.path, e))?;
+                removed.push(backup);
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Copies `config_path` to `config.$number.bak`, numbered after the latest
+    /// backup.
+    ///
+    /// Backups are never removed here, [`CargoRunner::prune_backups`] does that
+    /// with whatever policy the caller chooses. Returns `None` without doing
+    /// anything when there is no file to back up.
+    pub(crate) fn create_backup(config_path: &Path) -> Result<Option<PathBuf>> {
+        if !config_path.exists() {
+            return Ok(None);
+        }
+        // Held while numbering, so two processes never take the same index
+        let _lock = FileLock::acquire(config_path)?;
+
+        let index = match Self::list_backups(config_path)?.last() {
+            Some(latest) => latest.index + 1,
+            None => 0,
+        };
+        let backup_path =
+            config_path.with_file_name(format!("{}.{}.bak", backup_stem(config_path), index));
+        let content =
+            fs::read(config_path).map_err(|e| Error::io("failed to back up", config_path, e))?;
+        writer::replace(&backup_path, &content)?;
+        Ok(Some(backup_path))
+    }
+}
+
+fn backup_dir(config_path: &Path) -> PathBuf {
+    match config_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// `config` for `config.toml`, the part of backup names before the index.
+fn backup_stem(config_path: &Path) -> String {
+    config_path
+        .with_extension("")
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn backup_index(path: &Path, stem: &str) -> Option<u32> {
+    let name = path.file_name()?.to_str()?;
+    let index = name.strip_prefix(stem)?.strip_prefix('.')?.strip_suffix(".bak")?;
+    if !index.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    index.parse().ok()
+}
+
+/// Diffs two texts with the longest common subsequence of their lines.
+pub(crate) fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // common[i][j] is the length of the LCS of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(DiffLine::Same(old[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(DiffLine::Removed(old[i].to_string()));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(new[j].to_string()));
+            j += 1;
+        }
+    }
+    lines
+}
+
+struct DisplayAge(Duration);
+
+impl Display for DisplayAge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let seconds = self.0.as_secs();
+        let (amount, unit) = match seconds {
+            0..=59 => return write!(f, "just now"),
+            60..=3599 => (seconds / 60, "minute"),
+            3600..=86399 => (seconds / 3600, "hour"),
+            _ => (seconds / 86400, "day"),
+        };
+        let plural = if amount == 1 { "" } else { "s" };
+        write!(f, "{} {}{} ago", amount, unit, plural)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backup_names(config_path: &Path) -> Vec<String> {
+        CargoRunner::list_backups(config_path)
+            .unwrap()
+            .iter()
+            .map(|backup| backup.path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_backups_are_numbered_and_pruned() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(dir.path().join("config.old.bak"), "").unwrap();
+
+        for version in 0..12 {
+            fs::write(&path, format!("version = {}\n", version)).unwrap();
+            CargoRunner::create_backup(&path).unwrap();
+        }
+
+        // Taking backups never removes any, pruning keeps the last 10 by default
+        assert_eq!(CargoRunner::list_backups(&path).unwrap().len(), 12);
+        let removed = CargoRunner::prune_backups(&path, &RetentionPolicy::default()).unwrap();
+        assert_eq!(removed.iter().map(|b| b.index).collect::<Vec<_>>(), [0, 1]);
+        let backups = CargoRunner::list_backups(&path).unwrap();
+        assert_eq!(backups.len(), 10);
+        assert_eq!((backups[0].index, backups[9].index), (2, 11));
+        assert_eq!(backups[9].size, "version = 11\n".len() as u64);
+
+        let week_ago = SystemTime::now() - Duration::from_secs(7 * 86400);
+        fs::File::options()
+            .write(true)
+            .open(&backups[7].path)
+            .unwrap()
+            .set_modified(week_ago)
+            .unwrap();
+        let policy = RetentionPolicy {
+            keep_last: Some(5),
+            max_age: Some(Duration::from_secs(86400)),
+        };
+        let removed = CargoRunner::prune_backups(&path, &policy).unwrap();
+        assert_eq!(removed.len(), 6);
+        assert_eq!(
+            backup_names(&path),
+            ["config.7.bak", "config.8.bak", "config.10.bak", "config.11.bak"]
+        );
+        assert!(dir.path().join("config.old.bak").exists());
+    }
+
+    #[test]
+    fn test_diff_and_restore_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "[run]\ndefault = \"leptos\"\n").unwrap();
+        CargoRunner::create_backup(&path).unwrap();
+        fs::write(&path, "[run]\ndefault = \"dx\"\n").unwrap();
+
+        let backup = CargoRunner::find_backup(&path, None).unwrap();
+        let diff: Vec<String> = CargoRunner::diff_backup(&path, &backup)
+            .unwrap()
+            .iter()
+            .map(DiffLine::to_string)
+            .collect();
+        assert_eq!(diff, [" [run]", "-default = \"leptos\"", "+default = \"dx\""]);
+
+        let current = CargoRunner::restore_backup(&path, &backup).unwrap().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[run]\ndefault = \"leptos\"\n");
+        assert_eq!(fs::read_to_string(current).unwrap(), "[run]\ndefault = \"dx\"\n");
+        assert_eq!(backup_names(&path), ["config.0.bak", "config.1.bak"]);
+
+        assert!(matches!(
+            CargoRunner::find_backup(&path, Some(7)),
+            Err(Error::UnknownBackup { .. })
+        ));
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{source::sha256, writer, ConfigPaths, Error, Fetched, Fetcher, Result, Validators};
+
+/// Presets downloaded from URLs, kept in [`ConfigPaths::cache_dir`] along with
+/// the validators to revalidate them with.
+///
+/// Used as a [`Fetcher`] it serves presets from the cache alone, for working
+/// offline; [`PresetCache::revalidating`] asks the server whether they changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresetCache {
+    dir: PathBuf,
+}
+
+/// A preset in the [`PresetCache`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedPreset {
+    pub url: String,
+    #[serde(flatten)]
+    pub validators: Validators,
+    /// When the preset was last downloaded, in seconds since the Unix epoch.
+    pub fetched_at: u64,
+    pub content: String,
+}
+
+/// How [`CargoRunner::download_with`](crate::CargoRunner::download_with) uses the
+/// [`PresetCache`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CacheMode {
+    /// Ask the server whether cached presets changed, downloading those that did.
+    #[default]
+    Revalidate,
+    /// Only serve presets from the cache, failing with [`Error::NotCached`] for others.
+    Offline,
+}
+
+/// A [`Fetcher`] that revalidates the presets in a [`PresetCache`], see
+/// [`PresetCache::revalidating`].
+#[derive(Debug, Clone)]
+pub struct Revalidating<'a, F> {
+    cache: &'a PresetCache,
+    fetcher: F,
+}
+
+impl PresetCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        PresetCache { dir: dir.into() }
+    }
+
+    /// The cache in [`ConfigPaths::cache_dir`] of `paths`.
+    pub fn with_paths(paths: &ConfigPaths) -> Self {
+        PresetCache::new(paths.cache_dir())
+    }
+
+    /// The cache in the cargo-runner home, see [`ConfigPaths::from_env`].
+    pub fn from_env() -> Result<Self> {
+        Ok(PresetCache::with_paths(&ConfigPaths::from_env()?))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The cached copy of `url`, if it was downloaded before.
+    pub fn get(&self, url: &str) -> Result<Option<CachedPreset>> {
+        let path = self.entry_path(url);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Error::io("failed to read", path, e)),
+        };
+        let cached: CachedPreset =
+            toml::from_str(&content).map_err(|e| Error::parse(Some(path), e))?;
+        // Two URLs hashing the same would be astonishing, but never serve the wrong one
+        Ok(Some(cached).filter(|cached| cached.url == url))
+    }
+
+    /// Caches `content` as the latest copy of `url`.
+    pub fn insert(&self, url: &str, content: &str, validators: Validators) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| Error::io("failed to create directory", &self.dir, e))?;
+        let cached = CachedPreset {
+            url: url.to_string(),
+            validators,
+            fetched_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default(),
+            content: content.to_string(),
+        };
+        // Entries are replaced whole, the last of two concurrent downloads wins
+        writer::replace(&self.entry_path(url), toml::to_string(&cached)?.as_bytes())
+    }
+
+    /// A [`Fetcher`] that downloads through `fetcher`, sending the validators of
+    /// cached presets so unchanged ones are served from the cache.
+    pub fn revalidating<F: Fetcher>(&self, fetcher: F) -> Revalidating<'_, F> {
+        Revalidating {
+            cache: self,
+            fetcher,
+        }
+    }
+
+    /// The cached copy of `url`, if any, and the validators to revalidate it with.
+    pub(crate) fn lookup(&self, url: &str) -> Result<(Option<CachedPreset>, Validators)> {
+        let cached = self.get(url)?;
+        let validators = cached
+            .as_ref()
+            .map(|cached| cached.validators.clone())
+            .unwrap_or_default();
+        Ok((cached, validators))
+    }
+
+    /// The content of `url` given what revalidating `cached` returned, caching it.
+    pub(crate) fn settle(
+        &self,
+        url: &str,
+        cached: Option<CachedPreset>,
+        fetched: Fetched,
+    ) -> Result<String> {
+        match (fetched, cached) {
+            (Fetched::NotModified, Some(cached)) => Ok(cached.content),
+            (Fetched::NotModified, None) => {
+                Err(Error::network(url, "not modified, but there is no cached copy"))
+            }
+            (Fetched::Modified { content, validators }, _) => {
+                self.insert(url, &content, validators)?;
+                Ok(content)
+            }
+        }
+    }
+
+    fn entry_path(&self, url: &str) -> PathBuf {
+        self.dir.join(format!("{}.toml", sha256(url.as_bytes())))
+    }
+}
+
+impl Fetcher for PresetCache {
+    fn fetch(&self, url: &str) -> Result<String> {
+        match self.get(url)? {
+            Some(cached) => Ok(cached.content),
+            None => Err(Error::NotCached {
+                url: url.to_string(),
+            }),
+        }
+    }
+}
+
+impl<F: Fetcher> Fetcher for Revalidating<'_, F> {
+    fn fetch(&self, url: &str) -> Result<String> {
+        let (cached, validators) = self.cache.lookup(url)?;
+        // Without validators the server can only send the preset again
+        let fetched = self.fetcher.fetch_if_modified(url, &validators)?;
+        self.cache.settle(url, cached, fetched)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CargoRunner;
+
+    const PRESET: &str = "[run]\ndefault = \"dx\"\n\n[[run.config]]\nname = \"dx\"\n\
+                          command_type = \"shell\"\ncommand = \"dx\"\n";
+
+    #[test]
+    fn test_offline_serves_only_cached_presets() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = PresetCache::new(dir.path().join("cache"));
+        let url = "https://example.com/dx.toml";
+
+        let err = cache.fetch(url).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "https://example.com/dx.toml is not cached, download it while online first"
+        );
+
+        let online = |_: &str| Ok(PRESET.to_string());
+        assert_eq!(cache.revalidating(online).fetch(url).unwrap(), PRESET);
+        let save_path = dir.path().join("dx.toml");
+        CargoRunner::download_with_fetcher(url, Some(save_path.clone()), None, &cache).unwrap();
+        assert!(CargoRunner::load(save_path).unwrap().find("run".into(), "dx").is_some());
+    }
+
+    /// Serves [`PRESET`] with an ETag to two requests, and a 304 to those that send it
+    /// back, returning the `If-None-Match` of each request once done.
+    #[cfg(any(feature = "async", feature = "blocking"))]
+    fn serve_preset() -> (String, std::thread::JoinHandle<Vec<Option<String>>>) {
+        use std::{
+            io::{Read, Write},
+            net::TcpListener,
+            thread,
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/dx.toml", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut conditions = Vec::new();
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                    let read = stream.read(&mut buffer).unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                let request = String::from_utf8(request).unwrap().to_ascii_lowercase();
+                let condition = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("if-none-match: "))
+                    .map(str::to_string);
+                let response = match condition.as_deref() {
+                    Some("\"v1\"") => "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\n\
+                                       Connection: close\r\n\r\n"
+                        .to_string(),
+                    _ => format!(
+                        "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: {}\r\n\
+                         Connection: close\r\n\r\n{}",
+                        PRESET.len(),
+                        PRESET
+                    ),
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+                conditions.push(condition);
+            }
+            conditions
+        });
+        (url, server)
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn test_revalidates_against_http_server() {
+        use crate::HttpFetcher;
+
+        let (url, server) = serve_preset();
+        let dir = tempfile::tempdir().unwrap();
+        let cache = PresetCache::new(dir.path().join("cache"));
+        let fetcher = cache.revalidating(HttpFetcher::default());
+        let save_path = dir.path().join("dx.toml");
+        for _ in 0..2 {
+            let downloaded =
+                CargoRunner::download_with_fetcher(&url, Some(save_path.clone()), None, &fetcher)
+                    .unwrap();
+            assert_eq!(downloaded.sha256, sha256(PRESET.as_bytes()));
+        }
+        let conditions = server.join().unwrap();
+        assert_eq!(conditions, [None, Some("\"v1\"".to_string())]);
+
+        // The server is gone, the cached copy is all there is
+        let cached = cache.get(&url).unwrap().unwrap();
+        assert_eq!(cached.validators.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(cache.fetch(&url).unwrap(), PRESET);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_download_revalidates_and_works_offline() {
+        let (url, server) = serve_preset();
+        let dir = tempfile::tempdir().unwrap();
+        let paths = ConfigPaths::new(dir.path());
+        let save_path = dir.path().join("dx.toml");
+        for _ in 0..2 {
+            let downloaded = CargoRunner::download_with(
+                &url,
+                Some(save_path.clone()),
+                Some(&paths),
+                CacheMode::Revalidate,
+            )
+            .await
+            .unwrap();
+            assert_eq!(downloaded.sha256, sha256(PRESET.as_bytes()));
+        }
+        let conditions = tokio::task::spawn_blocking(|| server.join().unwrap()).await.unwrap();
+        assert_eq!(conditions, [None, Some("\"v1\"".to_string())]);
+
+        let offline = dir.path().join("offline.toml");
+        CargoRunner::download_with(&url, Some(offline.clone()), Some(&paths), CacheMode::Offline)
+            .await
+            .unwrap();
+        assert!(CargoRunner::load(offline).unwrap().find("run".into(), "dx").is_some());
+
+        let missing = format!("{}-missing", url);
+        let err = CargoRunner::download_with(&missing, None, Some(&paths), CacheMode::Offline)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotCached { .. }));
+    }
+}
@@ -0,0 +1,675 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table, TableLike, Value};
+
+use crate::{
+    migration::{self, MigrationReport},
+    writer::{self, FileVersion},
+    CargoRunner, Config, Context, Error, Result,
+};
+
+/// A config file that is edited in place.
+///
+/// Every mutation only touches the entries it changes, so comments, formatting
+/// and the order of contexts and configs written by the user are kept intact.
+/// Use [`CargoRunner`] for reading configs and [`ConfigDocument`] to write them back.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigDocument {
+    document: DocumentMut,
+    path: Option<PathBuf>,
+    /// What `path` held when it was loaded or last saved.
+    version: Option<FileVersion>,
+}
+
+impl FromStr for ConfigDocument {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // Refuse to edit anything that would not load as a config
+        s.parse::<CargoRunner>()?;
+
+        let mut document = s.parse::<DocumentMut>().map_err(|e| Error::Parse {
+            path: None,
+            span: e.span(),
+            message: e.to_string().trim_end().to_string(),
+        })?;
+
+        // Edits are made against the current layout, so older files are upgraded when saved
+        migration::migrate(&mut document);
+
+        Ok(ConfigDocument {
+            document,
+            path: None,
+            version: None,
+        })
+    }
+}
+
+impl Display for ConfigDocument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.document)
+    }
+}
+
+impl ConfigDocument {
+    /// Reads the config file at `path`, remembering it as the target of [`ConfigDocument::save`].
+    pub fn load(path: &Path) -> Result<Self> {
+        let (source, version) = writer::read(path)?;
+        let mut document: ConfigDocument =
+            source.parse().map_err(|e: Error| e.with_path(path))?;
+        document.path = Some(path.to_path_buf());
+        document.version = Some(version);
+        Ok(document)
+    }
+
+    /// Builds a new document laid out the same way `rx` writes configs.
+    pub fn from_runner(runner: &CargoRunner) -> Result<Self> {
+        let mut document = ConfigDocument::default();
+        migration::set_version(&mut document.document);
+        document.apply(runner)?;
+        Ok(document)
+    }
+
+    /// Upgrades the document to [`CONFIG_VERSION`](crate::CONFIG_VERSION).
+    ///
+    /// Documents are already upgraded when they are parsed, so this only
+    /// reports changes for documents edited by hand since.
+    pub fn migrate(&mut self) -> MigrationReport {
+        migration::migrate(&mut self.document)
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Writes the document back to the file it was loaded from.
+    ///
+    /// Fails with [`Error::Modified`] if the file was written by anyone else
+    /// since, instead of losing their changes.
+    pub fn save(&mut self) -> Result<()> {
+        let path = self.path.as_deref().ok_or(Error::MissingPath)?;
+        self.version = Some(writer::write(path, self.to_string().as_bytes(), self.version)?);
+        Ok(())
+    }
+
+    /// Writes the document to `path`, replacing whatever is there.
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        writer::write(path, self.to_string().as_bytes(), None)?;
+        Ok(())
+    }
+
+    /// The config described by the document.
+    pub fn runner(&self) -> Result<CargoRunner> {
+        self.to_string().parse()
+    }
+
+    pub fn set_default(&mut self, context: Context, name: &str) -> Result<()> {
+        let key = context.as_str();
+        if self.config_mut(key, name).is_none() {
+            return Err(Error::UnknownConfig {
+                context: key.to_string(),
+                name: name.to_string(),
+            });
+        }
+
+        self.set_context_default(key, Some(name))
+    }
+
+    /// Adds `config` to `context`, or updates the fields of the config with the same name.
+    pub fn add_config(&mut self, context: Context, config: &Config) -> Result<()> {
+        config.check_sub_command()?;
+        let key = context.as_str();
+        let current = self.runner()?;
+        let existing = current.find(context.clone(), &config.name).cloned();
+
+        match existing {
+            Some(existing) => {
+                let table = self.config_mut(key, &config.name).ok_or_else(|| {
+                    Error::UnknownConfig {
+                        context: key.to_string(),
+                        name: config.name.clone(),
+                    }
+                })?;
+                sync_config(table, &existing, config)
+            }
+            None => self.push_config(key, config),
+        }
+    }
+
+    /// Removes the config called `name` from `context`, returning it.
+    ///
+    /// Like [`CargoRunner::remove_config`], refuses to remove the context's default.
+    pub fn remove_config(&mut self, context: Context, name: &str) -> Result<Config> {
+        let key = context.as_str();
+        let runner = self.runner()?;
+        let removed = runner
+            .find(context.clone(), name)
+            .cloned()
+            .ok_or_else(|| Error::UnknownConfig {
+                context: key.to_string(),
+                name: name.to_string(),
+            })?;
+        if runner.get_default(context.clone()) == Some(name) {
+            return Err(Error::DefaultInUse {
+                context: key.to_string(),
+                name: name.to_string(),
+            });
+        }
+
+        self.remove_config_in(key, name);
+        Ok(removed)
+    }
+
+    fn remove_config_in(&mut self, key: &str, name: &str) {
+        if let Some(configs) = self
+            .document
+            .get_mut(key)
+            .and_then(Item::as_table_like_mut)
+            .and_then(|table| table.get_mut("config"))
+        {
+            match configs {
+                Item::ArrayOfTables(tables) => tables.retain(|t| !has_name(t, name)),
+                Item::Value(Value::Array(values)) => values.retain(|value| {
+                    !value
+                        .as_inline_table()
+                        .is_some_and(|t| has_name(t, name))
+                }),
+                _ => {}
+            }
+        }
+    }
+
+    /// Sets the env variable `key` on a config, returning its previous value.
+    pub fn set_env(
+        &mut self,
+        context: Context,
+        name: &str,
+        key: &str,
+        value: &str,
+    ) -> Result<Option<String>> {
+        let key_path = format!("{}.config.{}.env", context.as_str(), name);
+        let config = self.existing_config_mut(context, name)?;
+        if !config.contains_key("env") {
+            // Becomes `env = { .. }` when the config itself is an inline table
+            config.insert("env", Item::Table(Table::new()));
+        }
+
+        let env = config
+            .get_mut("env")
+            .and_then(Item::as_table_like_mut)
+            .ok_or(Error::NotATable(key_path))?;
+
+        let previous = env.get(key).and_then(Item::as_str).map(str::to_string);
+        set_value(env, key, Value::from(value));
+        Ok(previous)
+    }
+
+    /// Removes the env variable `key` from a config, returning its previous value.
+    pub fn remove_env(&mut self, context: Context, name: &str, key: &str) -> Result<Option<String>> {
+        let config = self.existing_config_mut(context, name)?;
+
+        Ok(config
+            .get_mut("env")
+            .and_then(Item::as_table_like_mut)
+            .and_then(|env| env.remove(key))
+            .and_then(|item| item.as_str().map(str::to_string)))
+    }
+
+    /// Merges `other` the same way [`CargoRunner::merge`] does and writes the result into the document.
+    pub fn merge(&mut self, other: CargoRunner) -> Result<()> {
+        let mut runner = self.runner()?;
+        runner.merge(other)?;
+        self.apply(&runner)
+    }
+
+    /// Edits the document until it describes `target`, touching only what differs.
+    pub fn apply(&mut self, target: &CargoRunner) -> Result<()> {
+        let current = self.runner()?;
+
+        for context in current.0.keys() {
+            if !target.0.contains_key(context) {
+                self.document.remove(context);
+            }
+        }
+
+        for (context, (default, configs)) in &target.0 {
+            let (current_default, current_configs) = current
+                .0
+                .get(context)
+                .cloned()
+                .unwrap_or_default();
+            let current_configs = current_configs.unwrap_or_default();
+            let configs = configs.as_deref().unwrap_or_default();
+
+            if current_default != *default {
+                self.set_context_default(context, default.as_deref())?;
+            }
+
+            for config in &current_configs {
+                if !configs.iter().any(|c| c.name == config.name) {
+                    self.remove_config_in(context, &config.name);
+                }
+            }
+
+            for config in configs {
+                match current_configs.iter().find(|c| c.name == config.name) {
+                    Some(existing) if existing == config => {}
+                    Some(existing) => {
+                        if let Some(table) = self.config_mut(context, &config.name) {
+                            sync_config(table, existing, config)?;
+                        }
+                    }
+                    None => self.push_config(context, config)?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn set_context_default(&mut self, context: &str, default: Option<&str>) -> Result<()> {
+        let item = self.context_item_mut(context);
+        if let Item::Table(table) = item {
+            // An implicit `[run]` created by `[[run.config]]` needs a header once it has values
+            table.set_implicit(false);
+        }
+
+        let table = item
+            .as_table_like_mut()
+            .ok_or_else(|| Error::NotATable(context.to_string()))?;
+        match default {
+            Some(default) => set_value(table, "default", Value::from(default)),
+            None => {
+                table.remove("default");
+            }
+        }
+        Ok(())
+    }
+
+    fn context_item_mut(&mut self, context: &str) -> &mut Item {
+        self.document.entry(context).or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        })
+    }
+
+    fn config_mut(&mut self, context: &str, name: &str) -> Option<&mut dyn TableLike> {
+        let configs = self
+            .document
+            .get_mut(context)?
+            .as_table_like_mut()?
+            .get_mut("config")?;
+
+        match configs {
+            Item::ArrayOfTables(tables) => tables
+                .iter_mut()
+                .find(|t| has_name(*t, name))
+                .map(|t| t as &mut dyn TableLike),
+            Item::Value(Value::Array(values)) => values
+                .iter_mut()
+                .filter_map(Value::as_inline_table_mut)
+                .find(|t| has_name(*t, name))
+                .map(|t| t as &mut dyn TableLike),
+            _ => None,
+        }
+    }
+
+    fn existing_config_mut(&mut self, context: Context, name: &str) -> Result<&mut dyn TableLike> {
+        let key = context.as_str();
+        self.config_mut(key, name)
+            .ok_or_else(|| Error::UnknownConfig {
+                context: key.to_string(),
+                name: name.to_string(),
+            })
+    }
+
+    fn push_config(&mut self, context: &str, config: &Config) -> Result<()> {
+        let canonical = canonical(config)?;
+        let context_table = self
+            .context_item_mut(context)
+            .as_table_like_mut()
+            .ok_or_else(|| Error::NotATable(context.to_string()))?;
+
+        match context_table.get_mut("config") {
+            Some(Item::Value(Value::Array(values))) => {
+                values.push(Value::InlineTable(canonical.into_inline_table()));
+            }
+            Some(Item::ArrayOfTables(tables)) => tables.push(expand_tables(canonical)),
+            _ => {
+                let mut tables = ArrayOfTables::new();
+                tables.push(expand_tables(canonical));
+                context_table.insert("config", Item::ArrayOfTables(tables));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn has_name(table: &dyn TableLike, name: &str) -> bool {
+    table.get("name").and_then(Item::as_str) == Some(name)
+}
+
+/// `config` as a table with the same formatting `toml` would give it.
+fn canonical(config: &Config) -> Result<Table> {
+    let document = toml_edit::ser::to_document(config)?;
+    Ok(document.as_table().clone())
+}
+
+/// Turns nested inline tables such as `env` into `[context.config.env]` sections,
+/// and arrays of them such as `pre` into `[[context.config.pre]]` sections.
+fn expand_tables(mut table: Table) -> Table {
+    for (_, item) in table.iter_mut() {
+        if let Some(expanded) = expand_item(item) {
+            *item = expanded;
+        }
+    }
+
+    table
+}
+
+fn expand_item(item: &Item) -> Option<Item> {
+    match item {
+        Item::Value(Value::InlineTable(inline)) => Some(Item::Table(inline.clone().into_table())),
+        Item::Value(Value::Array(values))
+            if !values.is_empty() && values.iter().all(Value::is_inline_table) =>
+        {
+            let mut tables = ArrayOfTables::new();
+            for inline in values.iter().filter_map(Value::as_inline_table) {
+                tables.push(inline.clone().into_table());
+            }
+            Some(Item::ArrayOfTables(tables))
+        }
+        _ => None,
+    }
+}
+
+/// Updates only the keys whose value differs between `current` and `target`.
+fn sync_config(table: &mut dyn TableLike, current: &Config, target: &Config) -> Result<()> {
+    sync_table(table, &canonical(current)?, &canonical(target)?);
+    Ok(())
+}
+
+fn sync_table(table: &mut dyn TableLike, current: &dyn TableLike, target: &dyn TableLike) {
+    for (key, _) in current.iter() {
+        if !target.contains_key(key) {
+            table.remove(key);
+        }
+    }
+
+    for (key, item) in target.iter() {
+        let old = current.get(key);
+        if old.is_some_and(|old| old.to_string() == item.to_string()) {
+            continue;
+        }
+
+        match (table.get_mut(key), old.and_then(Item::as_table_like), item.as_table_like()) {
+            (Some(existing), Some(old), Some(new)) if existing.is_table_like() => {
+                if let Some(existing) = existing.as_table_like_mut() {
+                    sync_table(existing, old, new);
+                }
+            }
+            _ => match (item, expand_item(item)) {
+                // Become inline again when `table` itself is an inline table
+                (Item::Value(Value::InlineTable(_)), Some(expanded)) if !table.contains_key(key) => {
+                    table.insert(key, expanded);
+                }
+                (Item::Value(Value::Array(_)), Some(Item::ArrayOfTables(new)))
+                    if table.get(key).is_none_or(Item::is_array_of_tables) =>
+                {
+                    match (table.get_mut(key), old.and_then(expand_item)) {
+                        (Some(Item::ArrayOfTables(existing)), Some(Item::ArrayOfTables(old))) => {
+                            sync_tables(existing, &old, &new)
+                        }
+                        _ => {
+                            table.insert(key, Item::ArrayOfTables(new));
+                        }
+                    }
+                }
+                (Item::Value(value), _) => set_value(table, key, value.clone()),
+                (other, _) => {
+                    table.insert(key, other.clone());
+                }
+            },
+        }
+    }
+}
+
+/// Syncs `[[..]]` sections one by one, so the ones that did not change keep their comments.
+fn sync_tables(tables: &mut ArrayOfTables, current: &ArrayOfTables, target: &ArrayOfTables) {
+    while tables.len() > target.len() {
+        tables.remove(tables.len() - 1);
+    }
+
+    for (index, new) in target.iter().enumerate() {
+        match (tables.get_mut(index), current.get(index)) {
+            (Some(existing), Some(old)) => sync_table(existing, old, new),
+            _ => tables.push(new.clone()),
+        }
+    }
+}
+
+/// Sets `key` to `value`, keeping the comments and whitespace around an existing value.
+fn set_value(table: &mut dyn TableLike, key: &str, mut value: Value) {
+    match table.get_mut(key) {
+        Some(Item::Value(existing)) => {
+            *value.decor_mut() = existing.decor().clone();
+            *existing = value;
+        }
+        Some(item) => *item = Item::Value(value),
+        None => {
+            table.insert(key, Item::Value(value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEPTOS: &str = r#"# Presets for leptos projects
+[run]
+default = "default" # switch to leptos with `rx default run leptos`
+
+[[run.config]]
+name = "default"
+command = "cargo"
+sub_command = "run"
+
+# Uses cargo-leptos
+[[run.config]]
+name = "leptos"
+command_type = "sub_command"
+command = "leptos"
+sub_command = "watch"
+
+[run.config.env]
+LEPTOS_ENV = "DEV" # dev server
+
+[[test.config]]
+name = "leptos"
+command = "leptos"
+sub_command = "test"
+"#;
+
+    #[test]
+    fn test_edits_keep_comments_and_order() {
+        let mut document: ConfigDocument = LEPTOS.parse().unwrap();
+
+        document.set_default(Context::Run, "leptos").unwrap();
+        document.set_default(Context::Test, "leptos").unwrap();
+        assert_eq!(
+            document
+                .set_env(Context::Run, "leptos", "LEPTOS_ENV", "PROD")
+                .unwrap(),
+            Some("DEV".to_string())
+        );
+        document
+            .set_env(Context::Run, "default", "RUST_LOG", "debug")
+            .unwrap();
+
+        let expected = LEPTOS
+            .replace(
+                "default = \"default\" # switch",
+                "default = \"leptos\" # switch",
+            )
+            .replace(
+                "sub_command = \"run\"\n",
+                "sub_command = \"run\"\n\n[run.config.env]\nRUST_LOG = \"debug\"\n",
+            )
+            .replace("\"DEV\" # dev server", "\"PROD\" # dev server")
+            .replace(
+                "[[test.config]]",
+                "[test]\ndefault = \"leptos\"\n\n[[test.config]]",
+            );
+        assert_eq!(document.to_string(), expected);
+    }
+
+    #[test]
+    fn test_add_and_remove_configs() {
+        let mut document: ConfigDocument = LEPTOS.parse().unwrap();
+        let build = Config {
+            name: "leptos".to_string(),
+            command: Some("leptos".to_string()),
+            sub_command: Some("build".to_string()),
+            ..Default::default()
+        };
+
+        document.add_config(Context::Build, &build).unwrap();
+        assert_eq!(
+            document.to_string(),
+            format!("{}\n[[build.config]]\nname = \"leptos\"\ncommand = \"leptos\"\nsub_command = \"build\"\n", LEPTOS)
+        );
+
+        let removed = document.remove_config(Context::Build, "leptos").unwrap();
+        assert_eq!(removed, build);
+        assert_eq!(
+            document.remove_env(Context::Run, "leptos", "LEPTOS_ENV").unwrap(),
+            Some("DEV".to_string())
+        );
+        assert_eq!(
+            document.to_string(),
+            LEPTOS.replace("LEPTOS_ENV = \"DEV\" # dev server\n", "")
+        );
+        assert!(matches!(
+            document.remove_config(Context::Bench, "leptos"),
+            Err(Error::UnknownConfig { .. })
+        ));
+    }
+
+    #[test]
+    fn test_merge_only_touches_changed_fields() {
+        let mut document: ConfigDocument = LEPTOS.parse().unwrap();
+        let preset: CargoRunner = r#"
+            [run]
+            default = "leptos"
+
+            [[run.config]]
+            name = "leptos"
+            sub_command = "serve"
+        "#
+        .parse()
+        .unwrap();
+
+        document.merge(preset).unwrap();
+
+        assert_eq!(
+            document.to_string(),
+            LEPTOS
+                .replace("default = \"default\" #", "default = \"leptos\" #")
+                .replace("sub_command = \"watch\"", "sub_command = \"serve\"")
+        );
+    }
+
+    #[test]
+    fn test_from_runner_skips_empty_tables() {
+        let runner = CargoRunner::default();
+        let document = ConfigDocument::from_runner(&runner).unwrap();
+        let toml = document.to_string();
+
+        assert!(!toml.contains("env]"));
+        assert!(!toml.contains("allowed_subcommands"));
+        assert_eq!(document.runner().unwrap(), runner);
+    }
+
+    #[test]
+    fn test_hooks_are_written_as_sections() {
+        let source = r#"[[run.config]]
+name = "leptos"
+command = "leptos"
+
+[[run.config.pre]]
+command = "npm" # tailwind
+args = ["run", "build:css"]
+
+[[run.config]]
+name = "dx"
+command = "dx"
+"#;
+        let mut document: ConfigDocument = source.parse().unwrap();
+        let hooks: CargoRunner = r#"
+            [[run.config]]
+            name = "leptos"
+            post = [{ command = "docker", args = ["compose", "down"], on_failure = "continue" }]
+
+            [[run.config.pre]]
+            command = "sqlx"
+            args = ["migrate", "run"]
+        "#
+        .parse()
+        .unwrap();
+
+        document.merge(hooks).unwrap();
+
+        let expected = source.replace(
+            "[[run.config]]\nname = \"dx\"",
+            r#"[[run.config.pre]]
+command = "sqlx"
+args = ["migrate", "run"]
+
+[[run.config.post]]
+command = "docker"
+args = ["compose", "down"]
+on_failure = "continue"
+
+[[run.config]]
+name = "dx""#,
+        );
+        assert_eq!(document.to_string(), expected);
+
+        let runner = document.runner().unwrap();
+        let document = ConfigDocument::from_runner(&runner).unwrap();
+        assert!(document.to_string().contains("[[run.config.post]]\n"));
+        assert_eq!(document.runner().unwrap(), runner);
+    }
+
+    #[test]
+    fn test_save_refuses_to_lose_concurrent_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, LEPTOS).unwrap();
+
+        let mut document = ConfigDocument::load(&path).unwrap();
+        document.set_default(Context::Run, "leptos").unwrap();
+        document.save().unwrap();
+        // Saving again only conflicts with writes made by others
+        document.set_env(Context::Run, "leptos", "LEPTOS_ENV", "PROD").unwrap();
+        document.save().unwrap();
+
+        let mut other = ConfigDocument::load(&path).unwrap();
+        other.set_default(Context::Run, "default").unwrap();
+        other.save().unwrap();
+
+        document.set_env(Context::Run, "leptos", "RUST_LOG", "debug").unwrap();
+        assert!(matches!(document.save(), Err(Error::Modified(_))));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), other.to_string());
+
+        let mut parsed: ConfigDocument = LEPTOS.parse().unwrap();
+        assert!(matches!(parsed.save(), Err(Error::MissingPath)));
+    }
+}
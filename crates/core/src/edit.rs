@@ -0,0 +1,255 @@
+use std::fmt::Display;
+
+use crate::{CargoRunner, Context, Error, Result};
+
+/// One edit made by a removal or rename on [`CargoRunner`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    RemovedConfig { context: String, name: String },
+    RenamedConfig { context: String, from: String, to: String },
+    ChangedDefault { context: String, from: String, to: String },
+    RemovedContext { context: String },
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::RemovedConfig { context, name } => {
+                write!(f, "removed config '{}' from '{}'", name, context)
+            }
+            Change::RenamedConfig { context, from, to } => {
+                write!(f, "renamed config '{}' to '{}' in '{}'", from, to, context)
+            }
+            Change::ChangedDefault { context, from, to } => {
+                write!(f, "changed the default of '{}' from '{}' to '{}'", context, from, to)
+            }
+            Change::RemovedContext { context } => write!(f, "removed context '{}'", context),
+        }
+    }
+}
+
+impl CargoRunner {
+    /// Removes the config `name` from `context`.
+    ///
+    /// Fails if the config is the context's default, so `default` never points at
+    /// a config that does not exist.
+    pub fn remove_config(&mut self, context: Context, name: &str) -> Result<Vec<Change>> {
+        let key = context.as_str();
+        let (default, configs) = self
+            .0
+            .get_mut(key)
+            .ok_or_else(|| Error::UnknownContext(key.to_string()))?;
+
+        let unknown = || Error::UnknownConfig {
+            context: key.to_string(),
+            name: name.to_string(),
+        };
+        let configs = configs.as_mut().ok_or_else(unknown)?;
+        let index = configs.iter().position(|c| c.name == name).ok_or_else(unknown)?;
+        if default.as_deref() == Some(name) {
+            return Err(Error::DefaultInUse {
+                context: key.to_string(),
+                name: name.to_string(),
+            });
+        }
+
+        configs.remove(index);
+        Ok(vec![Change::RemovedConfig {
+            context: key.to_string(),
+            name: name.to_string(),
+        }])
+    }
+
+    /// Removes every config called `name`, the inverse of [`CargoRunner::pluck`].
+    ///
+    /// Nothing is removed if the config is the default of any context.
+    pub fn remove_everywhere(&mut self, name: &str) -> Result<Vec<Change>> {
+        let contexts: Vec<String> = self
+            .0
+            .iter()
+            .filter(|(_, (_, configs))| configs.iter().flatten().any(|c| c.name == name))
+            .map(|(context, _)| context.clone())
+            .collect();
+
+        if let Some(context) = contexts
+            .iter()
+            .find(|context| self.get_default(Context::from(context.as_str())) == Some(name))
+        {
+            return Err(Error::DefaultInUse {
+                context: context.clone(),
+                name: name.to_string(),
+            });
+        }
+
+        let mut changes = Vec::new();
+        for context in contexts {
+            changes.extend(self.remove_config(Context::from(context.as_str()), name)?);
+        }
+        Ok(changes)
+    }
+
+    /// Renames the config `from` of `context` to `to`, and the context's `default`
+    /// with it when it named `from`.
+    pub fn rename_config(&mut self, context: Context, from: &str, to: &str) -> Result<Vec<Change>> {
+        let key = context.as_str();
+        let (default, configs) = self
+            .0
+            .get_mut(key)
+            .ok_or_else(|| Error::UnknownContext(key.to_string()))?;
+        let unknown = || Error::UnknownConfig {
+            context: key.to_string(),
+            name: from.to_string(),
+        };
+        let configs = configs.as_mut().ok_or_else(unknown)?;
+
+        if from != to && configs.iter().any(|c| c.name == to) {
+            return Err(Error::DuplicateConfig {
+                context: key.to_string(),
+                name: to.to_string(),
+            });
+        }
+        let config = configs.iter_mut().find(|c| c.name == from).ok_or_else(unknown)?;
+        if from == to {
+            return Ok(Vec::new());
+        }
+
+        config.name = to.to_string();
+        let mut changes = vec![Change::RenamedConfig {
+            context: key.to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+        }];
+        if default.as_deref() == Some(from) {
+            *default = Some(to.to_string());
+            changes.push(Change::ChangedDefault {
+                context: key.to_string(),
+                from: from.to_string(),
+                to: to.to_string(),
+            });
+        }
+        Ok(changes)
+    }
+
+    /// Removes `context` with its default and every one of its configs.
+    pub fn remove_context(&mut self, context: Context) -> Result<Vec<Change>> {
+        let key = context.as_str();
+        let (_, configs) = self
+            .0
+            .shift_remove(key)
+            .ok_or_else(|| Error::UnknownContext(key.to_string()))?;
+
+        let mut changes: Vec<Change> = configs
+            .into_iter()
+            .flatten()
+            .map(|config| Change::RemovedConfig {
+                context: key.to_string(),
+                name: config.name,
+            })
+            .collect();
+        changes.push(Change::RemovedContext {
+            context: key.to_string(),
+        });
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRESETS: &str = r#"
+        [run]
+        default = "leptos"
+
+        [[run.config]]
+        name = "leptos"
+        command_type = "subcommand"
+        command = "leptos"
+        sub_command = "watch"
+
+        [[run.config]]
+        name = "dx"
+        command = "dx"
+        sub_command = "serve"
+
+        [build]
+        default = "release"
+
+        [[build.config]]
+        name = "release"
+        profile = "release"
+
+        [[build.config]]
+        name = "dx"
+        command = "dx"
+        sub_command = "build"
+    "#;
+
+    #[test]
+    fn test_remove_config_and_everywhere() {
+        let mut runner: CargoRunner = PRESETS.parse().unwrap();
+
+        let err = runner.remove_config(Context::Run, "leptos").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "config 'leptos' is the default of context 'run', change the default first"
+        );
+        assert!(matches!(
+            runner.remove_config(Context::Run, "trunk"),
+            Err(Error::UnknownConfig { .. })
+        ));
+
+        let plucked = runner.pluck("dx");
+        let changes = runner.remove_everywhere("dx").unwrap();
+        assert_eq!(
+            changes.iter().map(Change::to_string).collect::<Vec<_>>(),
+            ["removed config 'dx' from 'run'", "removed config 'dx' from 'build'"]
+        );
+        assert!(runner.pluck("dx").0.is_empty());
+        assert_eq!(plucked.0.len(), 2);
+
+        // Nothing is removed when one of the contexts uses it as its default
+        let before = runner.clone();
+        assert!(matches!(
+            runner.remove_everywhere("release"),
+            Err(Error::DefaultInUse { .. })
+        ));
+        assert_eq!(runner, before);
+    }
+
+    #[test]
+    fn test_rename_config_and_remove_context() {
+        let mut runner: CargoRunner = PRESETS.parse().unwrap();
+
+        let changes = runner.rename_config(Context::Run, "leptos", "watch").unwrap();
+        assert_eq!(
+            changes,
+            [
+                Change::RenamedConfig {
+                    context: "run".to_string(),
+                    from: "leptos".to_string(),
+                    to: "watch".to_string(),
+                },
+                Change::ChangedDefault {
+                    context: "run".to_string(),
+                    from: "leptos".to_string(),
+                    to: "watch".to_string(),
+                },
+            ]
+        );
+        assert_eq!(runner.get_default(Context::Run), Some("watch"));
+        assert!(matches!(
+            runner.rename_config(Context::Run, "watch", "dx"),
+            Err(Error::DuplicateConfig { .. })
+        ));
+
+        let changes = runner.remove_context(Context::Build).unwrap();
+        assert_eq!(changes.len(), 3);
+        assert_eq!(changes[2].to_string(), "removed context 'build'");
+        assert!(!runner.0.contains_key("build"));
+        assert!(matches!(
+            runner.remove_context(Context::Build),
+            Err(Error::UnknownContext(_))
+        ));
+    }
+}
@@ -1,11 +1,30 @@
-use std::{collections::HashMap, fs, path::PathBuf};
-
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use indexmap::IndexMap;
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::{InstanceType, Metadata, Schema, SchemaObject},
+    JsonSchema,
+};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use super::{CommandType, Config, Context};
-
+use super::{CommandType, Config, Context, LoadOutcome, RecoveryPolicy};
+use crate::{
+    migration,
+    writer::{self, FileVersion},
+    ConfigDocument, ConfigPaths, Error, MergeOptions, Result, CONFIG_VERSION,
+};
+
+/// Configs keyed by context.
+///
+/// Contexts keep the order they were inserted in, so parsing, merging and
+/// serializing a config never reshuffles its sections.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct CargoRunner(pub HashMap<String, (Option<String>, Option<Vec<Config>>)>);
+pub struct CargoRunner(pub IndexMap<String, (Option<String>, Option<Vec<Config>>)>);
 
 impl Serialize for CargoRunner {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -14,7 +33,8 @@ impl Serialize for CargoRunner {
     {
         use serde::ser::SerializeMap;
 
-        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        let mut map = serializer.serialize_map(Some(self.0.len() + 1))?;
+        map.serialize_entry("version", &CONFIG_VERSION)?;
 
         for (key, (default, commands)) in &self.0 {
             #[derive(Serialize)]
@@ -36,34 +56,119 @@ impl Serialize for CargoRunner {
     }
 }
 
+/// A context table such as `[run]`.
+// Shared by `Deserialize` and the JSON Schema so the two cannot drift apart.
+#[derive(Deserialize, JsonSchema)]
+#[schemars(rename = "Context", deny_unknown_fields)]
+struct ContextEntry {
+    /// Name of the config used when none is given.
+    default: Option<String>,
+    /// Every config available in this context.
+    config: Option<Vec<Config>>,
+}
+
 // Custom deserialization implementation
 impl<'de> Deserialize<'de> for CargoRunner {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        #[derive(Deserialize)]
-        struct CommandEntry {
-            default: Option<String>,
-            config: Option<Vec<Config>>,
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = CargoRunner;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a table of contexts")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::MapAccess<'de>,
+            {
+                let mut contexts = IndexMap::new();
+
+                while let Some(key) = map.next_key::<String>()? {
+                    // `version` is the only top-level key that is not a context
+                    if key == "version" {
+                        let version: u32 = map.next_value()?;
+                        if version > CONFIG_VERSION {
+                            return Err(serde::de::Error::custom(format!(
+                                "config version {} is newer than the supported version {}, upgrade rx to read it",
+                                version, CONFIG_VERSION
+                            )));
+                        }
+                        continue;
+                    }
+
+                    let entry: ContextEntry = map.next_value()?;
+                    contexts.insert(key, (entry.default, entry.config));
+                }
+
+                Ok(CargoRunner(contexts))
+            }
         }
 
-        let map = HashMap::<String, CommandEntry>::deserialize(deserializer)?;
+        deserializer.deserialize_map(Visitor)
+    }
+}
 
-        let converted = map
-            .into_iter()
-            .map(|(k, v)| (k, (v.default, v.config)))
-            .collect();
+/// The top-level `version` plus any number of context tables.
+impl JsonSchema for CargoRunner {
+    fn schema_name() -> String {
+        "CargoRunner".to_string()
+    }
 
-        Ok(CargoRunner(converted))
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let mut version = SchemaObject {
+            instance_type: Some(InstanceType::Integer.into()),
+            metadata: Some(Box::new(Metadata {
+                description: Some("Version of the config layout, see `rx migrate`.".to_string()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        version.number().minimum = Some(0.0);
+        version.number().maximum = Some(CONFIG_VERSION.into());
+
+        let mut schema = SchemaObject {
+            instance_type: Some(InstanceType::Object.into()),
+            metadata: Some(Box::new(Metadata {
+                title: Some("cargo-runner.toml".to_string()),
+                description: Some(
+                    "Configs keyed by context, e.g. `run`, `build`, `test` or `bench`.".to_string(),
+                ),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let object = schema.object();
+        object.properties.insert("version".to_string(), version.into());
+        object.additional_properties = Some(Box::new(gen.subschema_for::<ContextEntry>()));
+
+        schema.into()
+    }
+}
+
+impl CargoRunner {
+    /// The JSON Schema of a config file, for editors such as Taplo.
+    ///
+    /// It is generated from the types, so it always matches what [`CargoRunner`] accepts.
+    pub fn json_schema() -> serde_json::Value {
+        // TOML has no null, so optional fields are simply not required
+        let schema = SchemaSettings::draft07()
+            .with(|settings| settings.option_add_null_type = false)
+            .into_generator()
+            .into_root_schema_for::<CargoRunner>();
+        serde_json::to_value(schema).expect("schemas always serialize to JSON")
     }
 }
 
 impl Default for CargoRunner {
     fn default() -> Self {
-        let mut commands = HashMap::new();
+        let mut commands = IndexMap::new();
 
-        // Add default commands
+        // Add default commands, in canonical context order
         commands.insert(
             "run".to_string(),
             (
@@ -73,38 +178,35 @@ impl Default for CargoRunner {
                     command_type: Some(CommandType::Cargo),
                     command: Some("cargo".to_string()),
                     sub_command: Some("run".to_string()),
-                    allowed_subcommands: Some(vec![]),
-                    env: Some(HashMap::new()),
+                    ..Default::default()
                 }]),
             ),
         );
 
         commands.insert(
-            "test".to_string(),
+            "build".to_string(),
             (
                 Some("default".to_string()),
                 Some(vec![Config {
                     name: "default".to_string(),
                     command_type: Some(CommandType::Cargo),
                     command: Some("cargo".to_string()),
-                    sub_command: Some("test".to_string()),
-                    allowed_subcommands: Some(vec![]),
-                    env: Some(HashMap::new()),
+                    sub_command: Some("build".to_string()),
+                    ..Default::default()
                 }]),
             ),
         );
 
         commands.insert(
-            "build".to_string(),
+            "test".to_string(),
             (
                 Some("default".to_string()),
                 Some(vec![Config {
                     name: "default".to_string(),
                     command_type: Some(CommandType::Cargo),
                     command: Some("cargo".to_string()),
-                    sub_command: Some("build".to_string()),
-                    allowed_subcommands: Some(vec![]),
-                    env: Some(HashMap::new()),
+                    sub_command: Some("test".to_string()),
+                    ..Default::default()
                 }]),
             ),
         );
@@ -118,8 +220,7 @@ impl Default for CargoRunner {
                     command_type: Some(CommandType::Cargo),
                     command: Some("cargo".to_string()),
                     sub_command: Some("bench".to_string()),
-                    allowed_subcommands: Some(vec![]),
-                    env: Some(HashMap::new()),
+                    ..Default::default()
                 }]),
             ),
         );
@@ -128,52 +229,94 @@ impl Default for CargoRunner {
     }
 }
 
-impl Into<String> for CargoRunner {
-    fn into(self) -> String {
-        toml::to_string_pretty(&self).expect("Failed to serialize config to TOML")
+/// Formats the config the same way `rx` writes config files.
+impl TryFrom<&CargoRunner> for String {
+    type Error = Error;
+
+    fn try_from(value: &CargoRunner) -> Result<Self> {
+        Ok(ConfigDocument::from_runner(value)?.to_string())
     }
 }
 
-impl From<String> for CargoRunner {
-    fn from(value: String) -> Self {
-        toml::from_str(&value).expect("Failed to convert String to Config")
+impl TryFrom<CargoRunner> for String {
+    type Error = Error;
+
+    fn try_from(value: CargoRunner) -> Result<Self> {
+        String::try_from(&value)
     }
 }
 
-impl From<&str> for CargoRunner {
-    fn from(value: &str) -> Self {
-        toml::from_str(value).expect("Failed to convert String to Config")
+impl FromStr for CargoRunner {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        toml::from_str(&migration::upgrade(s)).map_err(|e| Error::parse(None, e))
+    }
+}
+
+impl TryFrom<&str> for CargoRunner {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl TryFrom<String> for CargoRunner {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
     }
 }
 
 impl CargoRunner {
-    pub fn set_default(&mut self, context: Context, name: &str) -> Result<(), String> {
-        if let Some((_, configs)) = self.0.get(context.into()) {
-            if let Some(configs) = configs {
-                if configs.iter().any(|c| c.name == name) {
-                    self.0.insert(
-                        context.into(),
-                        (Some(name.to_string()), Some(configs.clone())),
-                    );
-                    return Ok(());
-                }
+    pub fn set_default(&mut self, context: Context, name: &str) -> Result<()> {
+        let (default, configs) = self
+            .0
+            .get_mut(context.as_str())
+            .ok_or_else(|| Error::UnknownContext(context.to_string()))?;
+
+        let exists = configs
+            .as_ref()
+            .is_some_and(|configs| configs.iter().any(|c| c.name == name));
+
+        if !exists {
+            return Err(Error::UnknownConfig {
+                context: context.to_string(),
+                name: name.to_string(),
+            });
+        }
+
+        *default = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Sorts contexts into canonical order: `run`, `build`, `test`, `bench`,
+    /// then any other context by name.
+    pub fn sort_contexts(&mut self) {
+        fn rank(context: &str) -> (usize, &str) {
+            match context {
+                "run" => (0, ""),
+                "build" => (1, ""),
+                "test" => (2, ""),
+                "bench" => (3, ""),
+                other => (4, other),
             }
         }
-        Err(format!(
-            "Command '{}' not found for type '{}'",
-            name, context
-        ))
+
+        self.0.sort_by(|a, _, b, _| rank(a).cmp(&rank(b)));
     }
 
     pub fn get_default(&self, context: Context) -> Option<&str> {
         self.0
-            .get(context.into())
+            .get(context.as_str())
             .and_then(|(default, _)| default.as_ref())
             .map(|s| s.as_str())
     }
 
     pub fn pluck(&self, config_name: &str) -> CargoRunner {
-        let mut found_configs = HashMap::new();
+        let mut found_configs = IndexMap::new();
 
         for (context, (_, configs)) in &self.0 {
             if let Some(configs_vec) = configs {
@@ -197,191 +340,128 @@ impl CargoRunner {
     }
 
     pub fn find(&self, context: Context, config_name: &str) -> Option<&Config> {
-        self.0.get(context.into()).and_then(|(_, configs)| {
+        self.0.get(context.as_str()).and_then(|(_, configs)| {
             configs
                 .as_ref()
                 .and_then(|configs_vec| configs_vec.iter().find(|c| c.name == config_name))
         })
     }
 
-    pub fn init() -> CargoRunner {
-        let home = dirs::home_dir().expect("Could not find home directory");
-        let config_dir = home.join(".cargo-runner");
-        let config_path = Self::get_default_config_path();
+    /// Loads the default config, writing it first if it does not exist yet. An
+    /// existing file in an older layout is upgraded with [`CargoRunner::migrate_file`];
+    /// an invalid one is reported, never overwritten.
+    pub fn init() -> Result<CargoRunner> {
+        Self::init_with(&ConfigPaths::from_env()?)
+    }
+
+    /// [`CargoRunner::init`] for the default config of `paths`.
+    pub fn init_with(paths: &ConfigPaths) -> Result<CargoRunner> {
+        let config_path = paths.config_file();
 
         // Create the config directory if it doesn't exist
-        fs::create_dir_all(&config_dir).expect("Failed to create config directory");
+        if let Some(config_dir) = config_path.parent() {
+            fs::create_dir_all(config_dir)
+                .map_err(|e| Error::io("failed to create directory", config_dir, e))?;
+        }
+
+        if !config_path.exists() {
+            let default_config = Self::default();
+            Self::write(&config_path, &default_config, Some(FileVersion::MISSING))?;
+            return Ok(default_config);
+        }
 
-        // Attempt to load the config
-        CargoRunner::load(config_path)
+        Ok(CargoRunner::load_and_migrate(&config_path)?.0)
     }
 
-    pub fn reset() {
-        let config_path = Self::get_default_config_path();
+    /// Replaces the default config file with [`CargoRunner::default`],
+    /// returning the path of the backup taken of the previous file.
+    ///
+    /// Fails with [`Error::Modified`] if the file is written by anyone else while
+    /// it is backed up, instead of losing changes the backup does not have.
+    pub fn reset() -> Result<Option<PathBuf>> {
+        Self::reset_with(&ConfigPaths::from_env()?)
+    }
 
-        let default_config = Self::default();
+    /// [`CargoRunner::reset`] for the default config of `paths`.
+    pub fn reset_with(paths: &ConfigPaths) -> Result<Option<PathBuf>> {
+        let config_path = paths.config_file();
 
-        Self::create_backup(&config_path);
+        let version = FileVersion::current(&config_path)?;
+        let backup = Self::create_backup(&config_path)?;
 
-        fs::write(
-            &config_path,
-            toml::to_string_pretty(&default_config).expect("Failed to serialize default config"),
-        )
-        .expect("Failed to write default config file");
-    }
-
-    pub async fn download(
-        url: &str,
-        save_path: Option<PathBuf>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        // Download the content from the URL asynchronously
-        let response = reqwest::get(url).await?;
-        let content = response.text().await?;
-
-        // Parse the fetched content as TOML into CargoRunner
-        let mut config: CargoRunner = toml::from_str(&content)?;
-
-        if let Some(path) = save_path {
-            if path.exists() {
-                // If the file already exists, load and merge it
-                let existing_content = fs::read_to_string(&path)?;
-                let mut existing_config: CargoRunner = toml::from_str(&existing_content)?;
-                
-                existing_config.merge(config.clone());
-                config = existing_config;
-            }
-            // Save the parsed configuration to the specified path
-            fs::create_dir_all(path.parent().unwrap())?;
-            let toml_content = toml::to_string_pretty(&config)?;
-            fs::write(&path, toml_content)?;
-        } else {
-            let mut default_config = Self::default();
-            default_config.merge(config.clone());
-
-            let config_path = Self::get_default_config_path();
-            // Save the parsed configuration to the default path
-            Self::create_backup(&config_path);
-
-            let toml = toml::to_string_pretty(&default_config)
-                .expect("Failed to serialize default config");
-            fs::write(config_path, toml).expect("Failed to write default config file");
-        }
+        Self::write(&config_path, &Self::default(), Some(version))?;
 
-        Ok(())
+        Ok(backup)
     }
 
-    fn get_default_config_path() -> PathBuf {
-        dirs::home_dir()
-            .expect("Could not find home directory")
-            .join(".cargo-runner")
-            .join("config.toml")
+    /// The default config file, see [`ConfigPaths::from_env`] for where it is.
+    pub fn get_default_config_path() -> Result<PathBuf> {
+        Ok(ConfigPaths::from_env()?.config_file())
     }
 
-    pub fn load(path: PathBuf) -> CargoRunner {
-        match fs::read_to_string(&path) {
-            Ok(data) => {
-                toml::from_str(&data).unwrap_or_else(|_| {
-                    eprintln!("Failed to parse config file from: {}", path.display());
-                    Self::create_backup(&path); // Create a backup on parse failure
-
-                    // Write the default config to the file
-                    let default_config = Self::default();
-                    let toml = toml::to_string_pretty(&default_config)
-                        .expect("Failed to serialize default config");
-                    fs::write(&path, toml).expect("Failed to write default config file");
-
-                    default_config // Return default config
-                })
-            }
-            Err(_) => {
-                eprintln!("Failed to read config path: {}", path.display());
-                Self::create_backup(&path); // Create a backup on read failure
+    /// Reads and parses the config at `path` without ever modifying it.
+    ///
+    /// Configs in an older layout are upgraded in memory only.
+    pub fn load(path: PathBuf) -> Result<CargoRunner> {
+        Self::read(&path)
+    }
 
-                // Write the default config to the file
+    /// Loads the config at `path`, applying `policy` if it cannot be read or parsed.
+    ///
+    /// The returned [`LoadOutcome`] tells the caller whether recovery happened,
+    /// why, and where the previous file was backed up to.
+    pub fn load_or_repair(
+        path: PathBuf,
+        policy: RecoveryPolicy,
+    ) -> Result<(CargoRunner, LoadOutcome)> {
+        let (error, version) = match writer::read(&path) {
+            Ok((data, version)) => match data.parse() {
+                Ok(config) => return Ok((config, LoadOutcome::Loaded)),
+                Err(e) => (e.with_path(&path), Some(version)),
+            },
+            Err(e) => (e, None),
+        };
+
+        match policy {
+            RecoveryPolicy::Error => Err(error),
+            RecoveryPolicy::BackupAndReset => {
+                // Never overwrite a file we failed to back up
+                let backup = Self::create_backup(&path)?;
+
+                // Leave the file alone if it was fixed in the meantime
                 let default_config = Self::default();
-                let toml = toml::to_string_pretty(&default_config)
-                    .expect("Failed to serialize default config");
-                fs::write(&path, toml).expect("Failed to write default config file");
+                Self::write(&path, &default_config, version)?;
 
-                default_config // Return default config
+                Ok((default_config, LoadOutcome::Reset { error, backup }))
+            }
+            RecoveryPolicy::KeepDefault => {
+                Ok((Self::default(), LoadOutcome::Defaulted { error }))
             }
         }
     }
 
-    pub fn merge(&mut self, other: CargoRunner) {
-        for (command_type, (other_default, other_configs)) in other.0 {
-            let command_type_clone = command_type.clone(); // Clone command_type for later use
-            let (base_default, base_configs) = self
-                .0
-                .entry(command_type_clone) // Use the cloned value here
-                .or_insert_with(|| (None, Some(Vec::new())));
-
-            // Merge command configurations first
-            if let Some(other_configs) = other_configs {
-                if let Some(base) = base_configs {
-                    for other_config in other_configs {
-                        // Check if the command already exists
-                        if let Some(existing) =
-                            base.iter_mut().find(|c| c.name == other_config.name)
-                        {
-                            existing.merge(&other_config); // Merge existing command
-                        } else {
-                            base.push(other_config.clone()); // Add new command
-                        }
-                    }
-                }
-            }
-
-            // Now update the default value if the other configuration has one
-            if let Some(ref new_default) = other_default {
-                // Check if the new default exists in the command list
-                if let Some(base) = base_configs {
-                    if base.iter().any(|cmd| cmd.name == *new_default) {
-                        *base_default = Some(new_default.clone()); // Update the default field
-                    } else {
-                        eprintln!(
-                            "Warning: Default command '{}' does not exist in the '{}' commands.",
-                            new_default, command_type
-                        );
-                        // Optionally, you can set it to None or keep the existing default
-                    }
-                }
-            }
-        }
+    fn read(path: &Path) -> Result<CargoRunner> {
+        let (data, _) = writer::read(path)?;
+        data.parse().map_err(|e: Error| e.with_path(path))
     }
 
-    fn create_backup(config_path: &PathBuf) {
-        let backup_path_with_index = config_path.with_extension(""); // Start with the original path without extension
-        let mut index = 0; // Start with 0
+    /// Writes `config` to `path`, failing if the file no longer matches `expected`.
+    fn write(path: &Path, config: &CargoRunner, expected: Option<FileVersion>) -> Result<()> {
+        let content = ConfigDocument::from_runner(config)?.to_string();
+        writer::write(path, content.as_bytes(), expected)?;
+        Ok(())
+    }
 
-        // Check if the backup file already exists and append an index if it does
-        loop {
-            let backup_file_name = format!(
-                "{}.{}.bak",
-                backup_path_with_index
-                    .file_stem()
-                    .unwrap()
-                    .to_str()
-                    .unwrap(),
-                index
-            );
-            let backup_path = backup_path_with_index.with_file_name(backup_file_name);
-
-            if !backup_path.exists() {
-                // Copy the original config file to the backup path
-                match fs::copy(config_path, &backup_path) {
-                    Ok(_) => {
-                        println!("Backup created at: {}", backup_path.display());
-                        break; // Exit the loop after creating the backup
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to create backup of the config file: {}", e);
-                        break; // Exit the loop on error
-                    }
-                }
-            }
-            index += 1; // Increment index for the next backup name
-        }
+    /// Merges `other` on top of this config, configs with the same name field by field.
+    ///
+    /// A `default` naming a config that does not exist is ignored, see
+    /// [`CargoRunner::merge_with`] for a report of what was merged.
+    ///
+    /// Nothing is merged if any resulting config has a `sub_command` outside of
+    /// its `allowed_subcommands`.
+    pub fn merge(&mut self, other: CargoRunner) -> Result<()> {
+        self.merge_with(other, &MergeOptions::default())?;
+        Ok(())
     }
 }
 
@@ -411,7 +491,8 @@ mod tests {
                 command: Some("dx".to_string()),
                 sub_command: Some("serve".to_string()),
                 allowed_subcommands: Some(vec![]),
-                env: Some(HashMap::new()),
+                env: Some(IndexMap::new()),
+                ..Default::default()
             });
 
         assert!(config.set_default(Context::Run, "dx").is_ok());
@@ -445,9 +526,9 @@ mod tests {
         assert_eq!(dx_config.sub_command, Some("serve".to_string()));
         assert!(matches!(dx_config.command_type, Some(CommandType::Shell)));
 
-        assert!(config.0.get("test").is_none());
-        assert!(config.0.get("build").is_none());
-        assert!(config.0.get("bench").is_none());
+        assert!(!config.0.contains_key("test"));
+        assert!(!config.0.contains_key("build"));
+        assert!(!config.0.contains_key("bench"));
     }
 
     #[test]
@@ -467,7 +548,7 @@ mod tests {
 
         let dx_config: CargoRunner = toml::from_str(dx_content).expect("Failed to parse dx config");
 
-        base_config.merge(dx_config);
+        base_config.merge(dx_config).unwrap();
 
         let (default, run_configs) = base_config.0.get("run").expect("Run config should exist");
         let run_configs = run_configs.as_ref().expect("Run config should have values");
@@ -493,4 +574,324 @@ mod tests {
         assert_eq!(default_config.sub_command, Some("run".to_string()));
         assert_eq!(default_config.command_type, Some(CommandType::Cargo));
     }
+
+    #[test]
+    fn test_parse_error_reports_path_and_span() {
+        let content = "[run]\ndefault = \"dx\"\n[[run.config]]\nname = \n";
+
+        let err = content.parse::<CargoRunner>().unwrap_err();
+        let err = err.with_path("cargo-runner.toml");
+
+        match err {
+            Error::Parse {
+                path,
+                span,
+                message,
+            } => {
+                assert_eq!(path, Some(PathBuf::from("cargo-runner.toml")));
+                let span = span.expect("parse errors should carry a span");
+                assert!(content[..span.start].ends_with("name = "));
+                assert!(message.contains("line 4"));
+            }
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_set_default_errors() {
+        let mut config = CargoRunner::default();
+
+        assert!(matches!(
+            config.set_default(Context::Run, "missing"),
+            Err(Error::UnknownConfig { context, name }) if context == "run" && name == "missing"
+        ));
+
+        config.0.shift_remove("bench");
+        assert!(matches!(
+            config.set_default(Context::Bench, "default"),
+            Err(Error::UnknownContext(context)) if context == "bench"
+        ));
+    }
+
+    #[test]
+    fn test_string_round_trip() {
+        let config = CargoRunner::default();
+
+        let toml = String::try_from(&config).expect("default config should serialize");
+        let parsed = CargoRunner::try_from(toml).expect("serialized config should parse");
+
+        assert_eq!(parsed, config);
+    }
+
+    #[test]
+    fn test_load_does_not_touch_invalid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "[run\n").unwrap();
+
+        assert!(matches!(
+            CargoRunner::load(path.clone()),
+            Err(Error::Parse { .. })
+        ));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[run\n");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_load_or_repair_policies() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "[run\n").unwrap();
+
+        assert!(CargoRunner::load_or_repair(path.clone(), RecoveryPolicy::Error).is_err());
+
+        let (config, outcome) =
+            CargoRunner::load_or_repair(path.clone(), RecoveryPolicy::KeepDefault).unwrap();
+        assert_eq!(config, CargoRunner::default());
+        assert!(matches!(outcome, LoadOutcome::Defaulted { .. }));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[run\n");
+
+        let (config, outcome) =
+            CargoRunner::load_or_repair(path.clone(), RecoveryPolicy::BackupAndReset).unwrap();
+        assert_eq!(config, CargoRunner::default());
+        match outcome {
+            LoadOutcome::Reset { backup, .. } => {
+                let backup = backup.expect("existing file should be backed up");
+                assert_eq!(backup, dir.path().join("config.0.bak"));
+                assert_eq!(fs::read_to_string(backup).unwrap(), "[run\n");
+            }
+            other => panic!("expected a reset, got {other:?}"),
+        }
+        assert_eq!(CargoRunner::load(path).unwrap(), CargoRunner::default());
+    }
+
+    #[test]
+    fn test_load_or_repair_missing_file_skips_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+
+        let (_, outcome) =
+            CargoRunner::load_or_repair(path.clone(), RecoveryPolicy::BackupAndReset).unwrap();
+
+        assert!(matches!(outcome, LoadOutcome::Reset { backup: None, .. }));
+        assert!(path.exists());
+        assert!(CargoRunner::list_backups(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_round_trip_keeps_order() {
+        let content = r#"version = 1
+
+[test]
+default = "nextest"
+
+[[test.config]]
+name = "nextest"
+command = "cargo"
+sub_command = "nextest"
+
+[test.config.env]
+RUST_LOG = "debug"
+CARGO_TERM_COLOR = "always"
+
+[run]
+default = "default"
+
+[[run.config]]
+name = "default"
+command = "cargo"
+sub_command = "run"
+"#;
+
+        let config: CargoRunner = content.parse().unwrap();
+        assert_eq!(config.0.keys().collect::<Vec<_>>(), ["test", "run"]);
+
+        assert_eq!(String::try_from(&config).unwrap(), content);
+        assert_eq!(
+            ConfigDocument::from_runner(&config).unwrap().to_string(),
+            content
+        );
+    }
+
+    #[test]
+    fn test_cargo_fields_round_trip() {
+        let content = r#"version = 1
+
+[test]
+default = "nextest"
+
+[[test.config]]
+name = "nextest"
+command_type = "subcommand"
+command = "nextest"
+sub_command = "run"
+toolchain = "nightly"
+profile = "ci"
+target = "x86_64-unknown-linux-gnu"
+features = ["ssr", "hydrate"]
+all_features = false
+no_default_features = true
+args = ["-p", "app"]
+extra_args = ["--nocapture"]
+"#;
+
+        let config: CargoRunner = content.parse().unwrap();
+        assert_eq!(String::try_from(&config).unwrap(), content);
+        assert_eq!(
+            ConfigDocument::from_runner(&config).unwrap().to_string(),
+            content
+        );
+
+        let mut merged = config.clone();
+        merged.merge(
+            "[[test.config]]\nname = \"nextest\"\nprofile = \"release\"\nfeatures = [\"ssr\", \"csr\"]\nargs = [\"--workspace\"]\n"
+                .parse()
+                .unwrap(),
+        )
+        .unwrap();
+        let nextest = merged.find(Context::Test, "nextest").unwrap();
+        assert_eq!(nextest.profile.as_deref(), Some("release"));
+        assert_eq!(nextest.features.as_deref().unwrap(), ["ssr", "hydrate", "csr"]);
+        assert_eq!(nextest.args.as_deref().unwrap(), ["-p", "app", "--workspace"]);
+        assert_eq!(nextest.toolchain.as_deref(), Some("nightly"));
+    }
+
+    #[test]
+    fn test_custom_contexts() {
+        let mut config: CargoRunner = r#"
+            [coverage]
+            default = "llvm-cov"
+
+            [[coverage.config]]
+            name = "llvm-cov"
+            command_type = "subcommand"
+            command = "llvm-cov"
+
+            [[coverage.config]]
+            name = "tarpaulin"
+            command_type = "subcommand"
+            command = "tarpaulin"
+        "#
+        .parse()
+        .unwrap();
+        let coverage: Context = "coverage".parse().unwrap();
+
+        assert_eq!(config.get_default(coverage.clone()), Some("llvm-cov"));
+        assert!(config.find(coverage.clone(), "tarpaulin").is_some());
+
+        config.set_default(coverage.clone(), "tarpaulin").unwrap();
+        assert_eq!(config.get_default(coverage), Some("tarpaulin"));
+
+        let doc = Context::Custom("doc".to_string());
+        assert!(matches!(
+            config.set_default(doc, "default"),
+            Err(Error::UnknownContext(context)) if context == "doc"
+        ));
+    }
+
+    #[test]
+    fn test_sort_contexts() {
+        let mut config: CargoRunner = r#"
+            [[coverage.config]]
+            name = "llvm-cov"
+            [[bench.config]]
+            name = "default"
+            [[doc.config]]
+            name = "default"
+            [[run.config]]
+            name = "default"
+        "#
+        .parse()
+        .unwrap();
+
+        config.merge(CargoRunner::default()).unwrap();
+        config.sort_contexts();
+
+        assert_eq!(
+            config.0.keys().collect::<Vec<_>>(),
+            ["run", "build", "test", "bench", "coverage", "doc"]
+        );
+    }
+
+    #[test]
+    fn test_merge_enforces_allowed_subcommands() {
+        let base: CargoRunner = r#"
+            [run]
+            default = "dx"
+
+            [[run.config]]
+            name = "dx"
+            command_type = "shell"
+            command = "dx"
+            sub_command = "serve"
+            allowed_subcommands = ["serve", "build"]
+        "#
+        .parse()
+        .unwrap();
+
+        let mut config = base.clone();
+        let err = config
+            .merge(
+                "[[run.config]]\nname = \"dx\"\nsub_command = \"bundle\"\n"
+                    .parse()
+                    .unwrap(),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "sub_command `bundle` is not allowed for config 'dx', expected one of `serve`, `build`"
+        );
+        assert_eq!(config, base);
+
+        config
+            .merge("[[run.config]]\nname = \"dx\"\nsub_command = \"build\"\n".parse().unwrap())
+            .unwrap();
+        let dx = config.find(Context::Run, "dx").unwrap();
+        assert_eq!(dx.sub_command.as_deref(), Some("build"));
+
+        // New configs are checked against their own list
+        let err = config
+            .merge(
+                "[[run.config]]\nname = \"trunk\"\nsub_command = \"watch\"\nallowed_subcommands = [\"serve\"]\n"
+                    .parse()
+                    .unwrap(),
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::SubCommandNotAllowed { .. }));
+    }
+
+    #[test]
+    fn test_set_sub_command() {
+        let mut config = Config {
+            name: "dx".to_string(),
+            sub_command: Some("serve".to_string()),
+            allowed_subcommands: Some(vec!["serve".to_string(), "build".to_string()]),
+            ..Default::default()
+        };
+
+        assert!(config.set_sub_command("bundle").is_err());
+        assert_eq!(config.sub_command.as_deref(), Some("serve"));
+        config.set_sub_command("build").unwrap();
+        assert_eq!(config.sub_command.as_deref(), Some("build"));
+
+        // An empty list allows anything
+        config.allowed_subcommands = Some(Vec::new());
+        config.set_sub_command("bundle").unwrap();
+        assert!(config.check_sub_command().is_ok());
+    }
+
+    #[test]
+    fn test_init_and_reset_with_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = ConfigPaths::new(dir.path().join("cargo-runner"));
+
+        let config = CargoRunner::init_with(&paths).unwrap();
+        assert_eq!(config, CargoRunner::default());
+        assert_eq!(CargoRunner::load(paths.config_file()).unwrap(), config);
+
+        fs::write(paths.config_file(), "[run]\ndefault = \"custom\"\n").unwrap();
+        let backup = CargoRunner::reset_with(&paths).unwrap().unwrap();
+        assert_eq!(backup, paths.home().join("config.0.bak"));
+        assert_eq!(CargoRunner::load(paths.config_file()).unwrap(), CargoRunner::default());
+    }
 }
@@ -1,4 +1,13 @@
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::str::FromStr;
+
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Metadata, Schema, SchemaObject, SubschemaValidation},
+    JsonSchema,
+};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::Error;
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub enum CommandType {
@@ -8,19 +17,64 @@ pub enum CommandType {
     Shell,
 }
 
-impl Into<String> for CommandType {
-    fn into(self) -> String {
+impl CommandType {
+    /// The canonical spelling of every variant, as written by [`Serialize`].
+    pub const VARIANTS: &'static [&'static str] = &["cargo", "subcommand", "shell"];
+
+    /// Other spellings [`FromStr`] accepts, each with the variant it stands for.
+    pub const ALIASES: &'static [(&'static str, &'static str)] =
+        &[("sub_command", "subcommand"), ("sub-command", "subcommand")];
+
+    /// What each variant does, in the order of [`CommandType::VARIANTS`].
+    pub fn description(&self) -> &'static str {
         match self {
-            CommandType::Cargo => "cargo".to_string(),
-            CommandType::SubCommand => "subcommand".to_string(),
-            CommandType::Shell => "shell".to_string(),
+            CommandType::Cargo => "Runs `cargo <sub_command>`.",
+            CommandType::SubCommand => {
+                "Runs a cargo subcommand installed as `cargo-<command>`, e.g. `cargo leptos watch`."
+            }
+            CommandType::Shell => "Runs `command` as a plain program.",
         }
     }
+
+    /// The variants as a human readable list, e.g. for error messages.
+    pub(crate) fn expected() -> String {
+        Self::VARIANTS
+            .iter()
+            .map(|v| format!("`{}`", v))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
 }
 
-impl Into<&str> for CommandType {
-    fn into(self) -> &'static str {
-        match self {
+/// Accepts the canonical names plus the [`CommandType::ALIASES`], i.e. the
+/// documented `sub_command` and `sub-command` for [`CommandType::SubCommand`].
+impl FromStr for CommandType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = Self::ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == s)
+            .map_or(s, |(_, name)| name);
+        match name {
+            "cargo" => Ok(CommandType::Cargo),
+            "subcommand" => Ok(CommandType::SubCommand),
+            "shell" => Ok(CommandType::Shell),
+            _ => Err(Error::UnknownCommandType(s.to_string())),
+        }
+    }
+}
+
+impl From<CommandType> for String {
+    fn from(command_type: CommandType) -> Self {
+        let name: &str = command_type.into();
+        name.to_string()
+    }
+}
+
+impl From<CommandType> for &'static str {
+    fn from(command_type: CommandType) -> Self {
+        match command_type {
             CommandType::Cargo => "cargo",
             CommandType::SubCommand => "subcommand",
             CommandType::Shell => "shell",
@@ -28,6 +82,44 @@ impl Into<&str> for CommandType {
     }
 }
 
+/// One `const` per canonical variant so editors can show each description, then
+/// one per alias accepted by [`FromStr`], described as such.
+impl JsonSchema for CommandType {
+    fn schema_name() -> String {
+        "CommandType".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        let constant = |name: &str, description: String| {
+            Schema::Object(SchemaObject {
+                instance_type: Some(InstanceType::String.into()),
+                const_value: Some(name.into()),
+                metadata: Some(Box::new(Metadata {
+                    description: Some(description),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            })
+        };
+        let variants = CommandType::VARIANTS.iter().map(|name| {
+            let variant: CommandType = name.parse().expect("variants always parse");
+            constant(name, variant.description().to_string())
+        });
+        let aliases = CommandType::ALIASES
+            .iter()
+            .map(|(alias, name)| constant(alias, format!("Alias of `{}`.", name)));
+        let variants = variants.chain(aliases).collect();
+
+        Schema::Object(SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                one_of: Some(variants),
+                ..Default::default()
+            })),
+            ..Default::default()
+        })
+    }
+}
+
 impl Serialize for CommandType {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -48,11 +140,33 @@ impl<'de> Deserialize<'de> for CommandType {
         D: Deserializer<'de>,
     {
         let s: String = Deserialize::deserialize(deserializer)?;
-        match s.as_str() {
-            "cargo" => Ok(CommandType::Cargo),
-            "subcommand" => Ok(CommandType::SubCommand),
-            "shell" => Ok(CommandType::Shell),
-            _ => Ok(CommandType::Cargo),
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aliases() {
+        for alias in ["subcommand", "sub_command", "sub-command"] {
+            assert_eq!(alias.parse::<CommandType>().unwrap(), CommandType::SubCommand);
         }
+        assert_eq!("cargo".parse::<CommandType>().unwrap(), CommandType::Cargo);
+        assert_eq!("shell".parse::<CommandType>().unwrap(), CommandType::Shell);
+
+        let config: crate::Config =
+            toml::from_str("name = \"leptos\"\ncommand_type = \"sub_command\"").unwrap();
+        assert_eq!(config.command_type, Some(CommandType::SubCommand));
+    }
+
+    #[test]
+    fn test_unknown_variant_is_rejected() {
+        let err = "sh".parse::<CommandType>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown command_type `sh`, expected one of `cargo`, `subcommand`, `shell`"
+        );
     }
 }
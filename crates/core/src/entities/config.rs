@@ -1,25 +1,105 @@
-use std::collections::HashMap;
+use indexmap::IndexMap;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize, Serializer};
 
-use super::CommandType;
+use super::{hook, CommandType, Hook};
+use crate::{merge::FieldMerger, Error, MergeOptions, Result};
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+/// A named way to run a context, e.g. `cargo leptos watch` for `run`.
+// Doc comments on the fields double as descriptions in the JSON Schema.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default, PartialEq, Eq)]
+#[schemars(deny_unknown_fields)]
 pub struct Config {
+    /// Name used to pick this config, e.g. with `rx default run <name>`.
     pub name: String,
+    /// How `command` is run. Defaults to `cargo`.
     #[serde(default)]
     #[serde(serialize_with = "serialize_command_type")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub command_type: Option<CommandType>,
+    /// The program to run, e.g. `cargo` or `dx`.
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub command: Option<String>,
+    /// The subcommand passed to `command`, e.g. `run` or `watch`.
     #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub sub_command: Option<String>,
+    /// Toolchain passed as `+toolchain`, e.g. `nightly`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub toolchain: Option<String>,
+    /// Build profile passed as `--profile`, e.g. `release`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Target triple passed as `--target`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Features passed as `--features`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_none_or_empty")]
+    pub features: Option<Vec<String>>,
+    /// Passes `--all-features`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub all_features: Option<bool>,
+    /// Passes `--no-default-features`.
     #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub no_default_features: Option<bool>,
+    /// Arguments passed after `sub_command`, e.g. `["-p", "app"]`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_none_or_empty")]
+    pub args: Option<Vec<String>>,
+    /// Arguments passed after `--`, e.g. `["--nocapture"]` for test binaries.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_none_or_empty")]
+    pub extra_args: Option<Vec<String>>,
+    /// Subcommands that may replace `sub_command`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_none_or_empty")]
     pub allowed_subcommands: Option<Vec<String>>,
+    /// Directory the command runs in. Relative paths are resolved against the
+    /// directory of the config file that sets it, or the package root.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Starts from an empty environment instead of inheriting the caller's.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clear_env: Option<bool>,
+    /// Inherited environment variables to unset, e.g. `["CARGO_TARGET_DIR"]`.
+    /// Also drops these keys from the `env` of configs merged below.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_none_or_empty")]
+    pub env_remove: Option<Vec<String>>,
+    /// Directories put in front of `PATH`, e.g. `["node_modules/.bin"]`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_none_or_empty")]
+    pub path_prepend: Option<Vec<String>>,
+    /// Commands run before this one, in order.
     #[serde(default)]
-    pub env: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "is_none_or_empty")]
+    pub pre: Option<Vec<Hook>>,
+    /// Commands run after this one succeeds, in order.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_none_or_empty")]
+    pub post: Option<Vec<Hook>>,
+    /// Environment variables set for the command.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_none_or_empty_map")]
+    pub env: Option<IndexMap<String, String>>,
+}
+
+fn is_none_or_empty<T>(value: &Option<Vec<T>>) -> bool {
+    value.as_ref().is_none_or(Vec::is_empty)
+}
+
+fn is_none_or_empty_map<K, V>(value: &Option<IndexMap<K, V>>) -> bool {
+    value.as_ref().is_none_or(IndexMap::is_empty)
 }
 
 fn serialize_command_type<S>(
@@ -40,29 +120,105 @@ where
 }
 
 impl Config {
-    pub fn merge(&mut self, other: &Config) {
+    /// Applies the fields `other` sets on top of this config, if both have the same name.
+    ///
+    /// - `args` and `extra_args` are appended
+    /// - `features`, `env_remove` and `path_prepend` are added unless already present
+    /// - `env` is extended after dropping the keys in `other.env_remove`
+    /// - `pre` and `post` hooks are appended, or replace the hook with the same `name`
+    /// - every other field is replaced
+    ///
+    /// The config is left untouched if the result would break [`Config::check_sub_command`].
+    /// Use [`CargoRunner::merge_with`](crate::CargoRunner::merge_with) to combine fields
+    /// differently.
+    pub fn merge(&mut self, other: &Config) -> Result<()> {
         // Only merge if names match
         if self.name != other.name {
-            return;
+            return Ok(());
         }
 
-        // Merge fields, keeping original values if other doesn't specify them
-        if let Some(cmd_type) = &other.command_type {
-            self.command_type = Some(cmd_type.clone());
-        }
-        if let Some(cmd) = &other.command {
-            self.command = Some(cmd.clone());
+        let mut merged = self.clone();
+        merged.merge_fields(other, &mut FieldMerger::new(&MergeOptions::default()));
+        merged.check_sub_command()?;
+        *self = merged;
+        Ok(())
+    }
+
+    /// Overrides `sub_command`, which must be one of `allowed_subcommands` when that is not empty.
+    pub fn set_sub_command(&mut self, sub_command: &str) -> Result<()> {
+        self.check_allowed(sub_command)?;
+        self.sub_command = Some(sub_command.to_string());
+        Ok(())
+    }
+
+    /// Checks that `sub_command` is one of `allowed_subcommands` when that is not empty.
+    pub fn check_sub_command(&self) -> Result<()> {
+        match &self.sub_command {
+            Some(sub_command) => self.check_allowed(sub_command),
+            None => Ok(()),
         }
-        if let Some(sub_cmd) = &other.sub_command {
-            self.sub_command = Some(sub_cmd.clone());
+    }
+
+    fn check_allowed(&self, sub_command: &str) -> Result<()> {
+        match &self.allowed_subcommands {
+            Some(allowed) if !allowed.is_empty() && !allowed.iter().any(|a| a == sub_command) => {
+                Err(Error::SubCommandNotAllowed {
+                    name: self.name.clone(),
+                    sub_command: sub_command.to_string(),
+                    allowed: allowed.clone(),
+                })
+            }
+            _ => Ok(()),
         }
-        if let Some(allowed) = &other.allowed_subcommands {
-            self.allowed_subcommands = Some(allowed.clone());
+    }
+
+    /// Merges every field but `name` through `fields`, see [`Config::merge`] for
+    /// what extending each field does.
+    pub(crate) fn merge_fields(&mut self, other: &Config, fields: &mut FieldMerger) {
+        let union: fn(&mut Vec<String>, &Vec<String>) = |base, values| add_missing(base, values);
+        let append: fn(&mut Vec<String>, &Vec<String>) = |base, args| base.extend_from_slice(args);
+        let hooks: fn(&mut Vec<Hook>, &Vec<Hook>) = |base, hooks| hook::merge_hooks(base, hooks);
+
+        fields.field("command_type", &mut self.command_type, &other.command_type, None);
+        fields.field("command", &mut self.command, &other.command, None);
+        fields.field("sub_command", &mut self.sub_command, &other.sub_command, None);
+        fields.field("toolchain", &mut self.toolchain, &other.toolchain, None);
+        fields.field("profile", &mut self.profile, &other.profile, None);
+        fields.field("target", &mut self.target, &other.target, None);
+        fields.field("features", &mut self.features, &other.features, Some(union));
+        fields.field("all_features", &mut self.all_features, &other.all_features, None);
+        fields.field(
+            "no_default_features",
+            &mut self.no_default_features,
+            &other.no_default_features,
+            None,
+        );
+        fields.field("args", &mut self.args, &other.args, Some(append));
+        fields.field("extra_args", &mut self.extra_args, &other.extra_args, Some(append));
+        fields.field(
+            "allowed_subcommands",
+            &mut self.allowed_subcommands,
+            &other.allowed_subcommands,
+            None,
+        );
+        fields.field("cwd", &mut self.cwd, &other.cwd, None);
+        fields.field("clear_env", &mut self.clear_env, &other.clear_env, None);
+        if fields.field("env_remove", &mut self.env_remove, &other.env_remove, Some(union)) {
+            if let (Some(env), Some(env_remove)) = (self.env.as_mut(), &other.env_remove) {
+                env.retain(|key, _| !env_remove.contains(key));
+            }
         }
-        // Merge environment variables if present
-        if let Some(other_env) = &other.env {
-            let base_env = self.env.get_or_insert_with(HashMap::new);
-            base_env.extend(other_env.clone());
+        fields.field("path_prepend", &mut self.path_prepend, &other.path_prepend, Some(union));
+        fields.field("pre", &mut self.pre, &other.pre, Some(hooks));
+        fields.field("post", &mut self.post, &other.post, Some(hooks));
+        fields.field("env", &mut self.env, &other.env, Some(|base, env| base.extend(env.clone())));
+    }
+}
+
+fn add_missing(base: &mut Vec<String>, values: &[String]) {
+    for value in values {
+        if !base.contains(value) {
+            base.push(value.clone());
         }
     }
 }
@@ -1,14 +1,37 @@
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
-#[derive(Debug, Clone, Copy)]
+use crate::Error;
+
+/// A section of the config such as `[run]`.
+///
+/// Any name other than the built-in ones is a [`Context::Custom`] context, e.g.
+/// `[doc]`, `[check]` or `[coverage]`. Parse names with [`FromStr`] so built-in
+/// names never end up as `Custom`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Context {
     Run,
     Build,
     Test,
     Bench,
+    Custom(String),
     None,
 }
 
+impl Context {
+    /// The name of the context's table, empty for [`Context::None`].
+    pub fn as_str(&self) -> &str {
+        match self {
+            Context::Run => "run",
+            Context::Build => "build",
+            Context::Test => "test",
+            Context::Bench => "bench",
+            Context::Custom(name) => name,
+            Context::None => "",
+        }
+    }
+}
+
+/// Maps an empty name to [`Context::None`] and any unknown name to [`Context::Custom`].
 impl From<&str> for Context {
     fn from(val: &str) -> Self {
         match val {
@@ -16,37 +39,58 @@ impl From<&str> for Context {
             "build" => Context::Build,
             "test" => Context::Test,
             "bench" => Context::Bench,
-            _ => Context::None,
+            "" => Context::None,
+            custom => Context::Custom(custom.to_string()),
         }
     }
 }
 
+/// Like `From<&str>`, but rejects names that cannot be a context table.
+impl FromStr for Context {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s == "version" || s.chars().any(|c| c == '.' || c.is_whitespace()) {
+            return Err(Error::InvalidContext(s.to_string()));
+        }
+
+        Ok(Context::from(s))
+    }
+}
+
 impl Display for Context {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self)
+        write!(f, "{}", self.as_str())
     }
 }
 
-impl Into<String> for Context {
-    fn into(self) -> String {
-        match self {
-            Context::Run => String::from("run"),
-            Context::Build => String::from("build"),
-            Context::Test => String::from("test"),
-            Context::Bench => String::from("bench"),
-            Context::None => String::new(),
+impl From<Context> for String {
+    fn from(context: Context) -> Self {
+        match context {
+            Context::Custom(name) => name,
+            other => other.as_str().to_string(),
         }
     }
 }
 
-impl Into<&str> for Context {
-    fn into(self) -> &'static str {
-        match self {
-            Context::Run => "run",
-            Context::Build => "build",
-            Context::Test => "test",
-            Context::Bench => "bench",
-            Context::None => "",
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for name in ["run", "build", "test", "bench", "doc", "coverage"] {
+            let context: Context = name.parse().unwrap();
+            assert_eq!(context.to_string(), name);
+        }
+
+        assert_eq!("run".parse::<Context>().unwrap(), Context::Run);
+        assert_eq!(
+            "clippy".parse::<Context>().unwrap(),
+            Context::Custom("clippy".to_string())
+        );
+        for invalid in ["", "version", "run.config", "my context"] {
+            assert!(invalid.parse::<Context>().is_err(), "{}", invalid);
         }
     }
 }
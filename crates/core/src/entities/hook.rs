@@ -0,0 +1,116 @@
+use indexmap::IndexMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A command run before or after a config's command, e.g. `npm run build:css`.
+///
+/// Hooks run with the config's environment plus their own `env`, in their own
+/// `cwd` or else the config's.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default, PartialEq, Eq)]
+#[schemars(deny_unknown_fields)]
+pub struct Hook {
+    /// Lets a config merged on top replace this hook instead of adding another one.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The program to run, e.g. `npm` or `sqlx`.
+    pub command: String,
+    /// Arguments passed to `command`, e.g. `["run", "build:css"]`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub args: Option<Vec<String>>,
+    /// Directory the hook runs in, resolved like the config's `cwd`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// What happens when the hook fails. Defaults to `fail_fast`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_failure: Option<FailurePolicy>,
+    /// Environment variables set for the hook on top of the config's.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<IndexMap<String, String>>,
+}
+
+/// What happens when a hook exits with an error.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailurePolicy {
+    /// Stop without running the rest of the hooks or the command.
+    #[default]
+    FailFast,
+    /// Carry on with the next hook or the command.
+    Continue,
+}
+
+/// Adds `hooks` after `base`; a named hook replaces the one with the same name
+/// in place, and hooks already in `base` are not added twice.
+pub(crate) fn merge_hooks(base: &mut Vec<Hook>, hooks: &[Hook]) {
+    for hook in hooks {
+        let same_name = hook
+            .name
+            .as_ref()
+            .and_then(|name| base.iter().position(|h| h.name.as_ref() == Some(name)));
+
+        match same_name {
+            Some(index) => base[index] = hook.clone(),
+            None if !base.contains(hook) => base.push(hook.clone()),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CargoRunner, Context};
+
+    #[test]
+    fn test_merge_hooks() {
+        let mut runner: CargoRunner = r#"
+            [[run.config]]
+            name = "leptos"
+            command = "leptos"
+
+            [[run.config.pre]]
+            name = "css"
+            command = "npm"
+            args = ["run", "build:css"]
+
+            [[run.config.pre]]
+            command = "sqlx"
+            args = ["migrate", "run"]
+        "#
+        .parse()
+        .unwrap();
+        let overrides: CargoRunner = r#"
+            [[run.config]]
+            name = "leptos"
+
+            [[run.config.pre]]
+            name = "css"
+            command = "pnpm"
+            args = ["build:css"]
+
+            [[run.config.pre]]
+            command = "sqlx"
+            args = ["migrate", "run"]
+
+            [[run.config.post]]
+            command = "docker"
+            on_failure = "continue"
+        "#
+        .parse()
+        .unwrap();
+
+        runner.merge(overrides).unwrap();
+
+        let config = runner.find(Context::Run, "leptos").unwrap();
+        let pre: Vec<_> = config.pre.iter().flatten().map(|h| h.command.as_str()).collect();
+        assert_eq!(pre, ["pnpm", "sqlx"]);
+        let post = &config.post.as_ref().unwrap()[0];
+        assert_eq!(post.on_failure, Some(FailurePolicy::Continue));
+    }
+}
@@ -2,8 +2,16 @@ mod config;
 mod command_type;
 mod cargo_runner;
 mod context;
+mod diagnostic;
+mod hook;
+mod parse_mode;
+mod recovery;
 
 pub use config::Config;
 pub use command_type::CommandType;
 pub use cargo_runner::CargoRunner;
 pub use context::Context;
+pub use diagnostic::{Diagnostic, Position, Severity, Span};
+pub use hook::{FailurePolicy, Hook};
+pub use parse_mode::ParseMode;
+pub use recovery::{LoadOutcome, RecoveryPolicy};
@@ -0,0 +1,10 @@
+/// How strictly a config is checked when it is parsed or validated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Reject values that cannot be understood, such as an unknown `command_type`.
+    #[default]
+    Strict,
+    /// Fall back to the default for values that cannot be understood
+    /// and report them as warnings instead of errors.
+    Lenient,
+}
@@ -0,0 +1,42 @@
+use std::path::PathBuf;
+
+use crate::Error;
+
+/// What [`CargoRunner::load_or_repair`](super::CargoRunner::load_or_repair) should do
+/// when a config file cannot be read or parsed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecoveryPolicy {
+    /// Return the error and leave the file untouched.
+    #[default]
+    Error,
+    /// Back up the file (if it exists) and overwrite it with the default config.
+    BackupAndReset,
+    /// Use the default config in memory without writing anything to disk.
+    KeepDefault,
+}
+
+/// How a config was obtained by [`CargoRunner::load_or_repair`](super::CargoRunner::load_or_repair).
+#[derive(Debug)]
+pub enum LoadOutcome {
+    /// The file was read and parsed successfully.
+    Loaded,
+    /// The file was unusable and has been replaced with the default config.
+    ///
+    /// `backup` is `None` when there was no file to back up.
+    Reset {
+        error: Error,
+        backup: Option<PathBuf>,
+    },
+    /// The file was unusable and the default config is being used in memory only.
+    Defaulted { error: Error },
+}
+
+impl LoadOutcome {
+    /// The error that triggered recovery, if any.
+    pub fn error(&self) -> Option<&Error> {
+        match self {
+            LoadOutcome::Loaded => None,
+            LoadOutcome::Reset { error, .. } | LoadOutcome::Defaulted { error } => Some(error),
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use std::{fmt, io, ops::Range, path::PathBuf};
+
+/// Errors returned by every fallible [`CargoRunner`](crate::CargoRunner) operation.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The TOML could not be parsed into a [`CargoRunner`](crate::CargoRunner).
+    ///
+    /// `span` is the byte range in the source that the parser blamed, when known.
+    #[error("failed to parse {}: {message}", DisplayPath(.path))]
+    Parse {
+        path: Option<PathBuf>,
+        span: Option<Range<usize>>,
+        message: String,
+    },
+
+    /// The config could not be serialized back to TOML.
+    #[error("failed to serialize config: {0}")]
+    Serialize(#[from] toml::ser::Error),
+
+    /// A config could not be serialized into a [`ConfigDocument`](crate::ConfigDocument).
+    #[error("failed to serialize config: {0}")]
+    SerializeDocument(#[from] toml_edit::ser::Error),
+
+    /// An entry of a [`ConfigDocument`](crate::ConfigDocument) that must be a table is not,
+    /// such as a context set to a string.
+    #[error("`{0}` is not a table")]
+    NotATable(String),
+
+    /// [`ConfigDocument::save`](crate::ConfigDocument::save) on a document that
+    /// was not loaded from a file.
+    #[error("the config was not loaded from a file, save it to a path instead")]
+    MissingPath,
+
+    #[error("{action} {}: {source}", .path.display())]
+    Io {
+        action: &'static str,
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The file was written by someone else since it was read, saving would lose their changes.
+    #[error("{} was changed since it was read, load it again and retry", .0.display())]
+    Modified(PathBuf),
+
+    #[error(
+        "could not find the home directory, set CARGO_RUNNER_HOME to choose where configs are kept"
+    )]
+    MissingHomeDir,
+
+    #[error("context '{0}' does not exist")]
+    UnknownContext(String),
+
+    #[error("`{0}` is not a valid context name")]
+    InvalidContext(String),
+
+    #[error("context '{0}' has no default config")]
+    MissingDefault(String),
+
+    #[error("config '{name}' not found for context '{context}'")]
+    UnknownConfig { context: String, name: String },
+
+    #[error("config '{name}' already exists in context '{context}'")]
+    DuplicateConfig { context: String, name: String },
+
+    /// Removing the config would leave its context's `default` pointing at nothing.
+    #[error("config '{name}' is the default of context '{context}', change the default first")]
+    DefaultInUse { context: String, name: String },
+
+    /// A `sub_command` that is not one of the config's `allowed_subcommands`.
+    #[error(
+        "sub_command `{sub_command}` is not allowed for config '{name}', expected one of {}",
+        DisplayList(.allowed)
+    )]
+    SubCommandNotAllowed {
+        name: String,
+        sub_command: String,
+        allowed: Vec<String>,
+    },
+
+    /// Fields merged with [`MergeStrategy::ErrorOnConflict`](crate::MergeStrategy::ErrorOnConflict)
+    /// that were set differently on both sides.
+    #[error(
+        "conflicting values for {}, keep one or change the merge strategy",
+        DisplayList(.0)
+    )]
+    MergeConflict(Vec<String>),
+
+    #[error("could not find {} of {}", DisplayBackup(.index), .path.display())]
+    UnknownBackup { path: PathBuf, index: Option<u32> },
+
+    #[error("unknown command_type `{0}`, expected one of {}", crate::CommandType::expected())]
+    UnknownCommandType(String),
+
+    /// A `${..}` placeholder could not be expanded.
+    #[error("failed to expand `{input}`: {message}")]
+    Interpolation { input: String, message: String },
+
+    #[error("failed to download {url}: {message}")]
+    Network { url: String, message: String },
+
+    /// A preset fetched offline that was never downloaded, see [`PresetCache`](crate::PresetCache).
+    #[error("{url} is not cached, download it while online first")]
+    NotCached { url: String },
+
+    /// A `git` command run to read a [`Source`](crate::Source) from a repository failed.
+    #[error("failed to read {spec} from git: {message}")]
+    Git { spec: String, message: String },
+
+    /// A download source that is not one of the forms [`Source`](crate::Source) accepts.
+    #[error("invalid source `{spec}`: {message}")]
+    InvalidSource { spec: String, message: String },
+
+    /// Downloaded content that does not match the `sha256=` its source is pinned to.
+    #[error("checksum mismatch for {spec}: expected sha256 {expected}, got {actual}")]
+    ChecksumMismatch {
+        spec: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub(crate) fn parse(path: Option<PathBuf>, error: toml::de::Error) -> Self {
+        Error::Parse {
+            path,
+            span: error.span(),
+            message: error.to_string().trim_end().to_string(),
+        }
+    }
+
+    pub(crate) fn io(action: &'static str, path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Error::Io {
+            action,
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// A [`Error::Network`] for `url`, for [`Fetcher`](crate::Fetcher) implementations.
+    pub fn network(url: &str, error: impl fmt::Display) -> Self {
+        Error::Network {
+            url: url.to_string(),
+            message: error.to_string(),
+        }
+    }
+
+    /// Attaches a file path to a parse error that was produced from a string.
+    pub(crate) fn with_path(self, path: impl Into<PathBuf>) -> Self {
+        match self {
+            Error::Parse { span, message, .. } => Error::Parse {
+                path: Some(path.into()),
+                span,
+                message,
+            },
+            other => other,
+        }
+    }
+}
+
+struct DisplayList<'a>(&'a [String]);
+
+impl fmt::Display for DisplayList<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, item) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "`{}`", item)?;
+        }
+        Ok(())
+    }
+}
+
+struct DisplayBackup<'a>(&'a Option<u32>);
+
+impl fmt::Display for DisplayBackup<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(index) => write!(f, "backup {}", index),
+            None => write!(f, "a backup"),
+        }
+    }
+}
+
+struct DisplayPath<'a>(&'a Option<PathBuf>);
+
+impl fmt::Display for DisplayPath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(path) => write!(f, "{}", path.display()),
+            None => write!(f, "config"),
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use std::{fs, path::PathBuf};
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    CargoRunner, ConfigDocument, ConfigPaths, Downloaded, Error, Location, LockedPreset, Lockfile,
+    Result, Source,
+};
+#[cfg(feature = "async")]
+use crate::CacheMode;
+#[cfg(any(feature = "async", feature = "blocking"))]
+use crate::PresetCache;
+
+/// Fetches the text of a config from an http(s) URL for
+/// [`CargoRunner::download_with_fetcher`].
+///
+/// Lets hosts without a network stack of their own, such as the WASM component
+/// of the VS Code extension, provide downloads. Closures taking the URL work too.
+pub trait Fetcher {
+    fn fetch(&self, url: &str) -> Result<String>;
+
+    /// Fetches `url` unless it still matches the `validators` of an earlier fetch,
+    /// what [`PresetCache::revalidating`](crate::PresetCache::revalidating) uses.
+    ///
+    /// Fetchers that cannot make conditional requests fetch it every time.
+    fn fetch_if_modified(&self, url: &str, validators: &Validators) -> Result<Fetched> {
+        let _ = validators;
+        Ok(Fetched::Modified {
+            content: self.fetch(url)?,
+            validators: Validators::default(),
+        })
+    }
+}
+
+/// The `ETag` and `Last-Modified` of a response, sent back to revalidate it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Validators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+/// The result of [`Fetcher::fetch_if_modified`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fetched {
+    Modified {
+        content: String,
+        validators: Validators,
+    },
+    /// The copy fetched earlier is still current.
+    NotModified,
+}
+
+impl<F> Fetcher for F
+where
+    F: Fn(&str) -> Result<String>,
+{
+    fn fetch(&self, url: &str) -> Result<String> {
+        self(url)
+    }
+}
+
+/// Fetches over HTTP(S) with a blocking client, what [`CargoRunner::download_blocking`] uses.
+#[cfg(feature = "blocking")]
+#[derive(Debug, Clone, Default)]
+pub struct HttpFetcher {
+    client: reqwest::blocking::Client,
+}
+
+#[cfg(feature = "blocking")]
+impl HttpFetcher {
+    pub fn new(client: reqwest::blocking::Client) -> Self {
+        HttpFetcher { client }
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl Fetcher for HttpFetcher {
+    fn fetch(&self, url: &str) -> Result<String> {
+        self.client
+            .get(url)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.text())
+            .map_err(|e| Error::network(url, e))
+    }
+
+    fn fetch_if_modified(&self, url: &str, validators: &Validators) -> Result<Fetched> {
+        let response = self
+            .client
+            .get(url)
+            .headers(validators.header_map())
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| Error::network(url, e))?;
+        match Validators::from_response(response.status(), response.headers()) {
+            Some(validators) => Ok(Fetched::Modified {
+                content: response.text().map_err(|e| Error::network(url, e))?,
+                validators,
+            }),
+            None => Ok(Fetched::NotModified),
+        }
+    }
+}
+
+/// [`Fetcher::fetch_if_modified`] for [`CargoRunner::download_with`], in an async runtime.
+#[cfg(feature = "async")]
+async fn fetch_if_modified_async(url: &str, validators: &Validators) -> Result<Fetched> {
+    let response = reqwest::Client::new()
+        .get(url)
+        .headers(validators.header_map())
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| Error::network(url, e))?;
+    match Validators::from_response(response.status(), response.headers()) {
+        Some(validators) => Ok(Fetched::Modified {
+            content: response.text().await.map_err(|e| Error::network(url, e))?,
+            validators,
+        }),
+        None => Ok(Fetched::NotModified),
+    }
+}
+
+impl Validators {
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+
+    /// The conditional request headers to send for these validators.
+    pub fn headers(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        let etag = self.etag.as_deref().map(|etag| ("if-none-match", etag));
+        let last_modified = self
+            .last_modified
+            .as_deref()
+            .map(|date| ("if-modified-since", date));
+        etag.into_iter().chain(last_modified)
+    }
+
+    /// [`Validators::headers`] to send with a request, skipping values that are
+    /// not valid header values.
+    #[cfg(any(feature = "async", feature = "blocking"))]
+    fn header_map(&self) -> reqwest::header::HeaderMap {
+        self.headers()
+            .filter_map(|(name, value)| Some((name, value.parse().ok()?)))
+            .map(|(name, value)| (reqwest::header::HeaderName::from_static(name), value))
+            .collect()
+    }
+
+    /// The validators of a response, `None` when it says the copy sent with
+    /// [`Validators::header_map`] is still current.
+    #[cfg(any(feature = "async", feature = "blocking"))]
+    fn from_response(
+        status: reqwest::StatusCode,
+        headers: &reqwest::header::HeaderMap,
+    ) -> Option<Self> {
+        if status == reqwest::StatusCode::NOT_MODIFIED {
+            return None;
+        }
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        Some(Validators {
+            etag: header(reqwest::header::ETAG),
+            last_modified: header(reqwest::header::LAST_MODIFIED),
+        })
+    }
+}
+
+impl CargoRunner {
+    /// Downloads the config at `source`, any form [`Source`] accepts, and merges it
+    /// into `save_path`, creating the file if needed.
+    ///
+    /// Without a `save_path` the config is merged into the default config, which
+    /// is backed up first, or created from [`CargoRunner::default`]. Nothing is
+    /// written if the content does not match the `sha256=` the source is pinned to.
+    ///
+    /// The preset is recorded in the [`Lockfile`] next to the config, for
+    /// [`CargoRunner::update_with_fetcher`] to fetch it again later. URLs are kept
+    /// in the [`PresetCache`](crate::PresetCache) and only downloaded again once
+    /// they changed.
+    #[cfg(feature = "async")]
+    pub async fn download(source: &str, save_path: Option<PathBuf>) -> Result<Downloaded> {
+        Self::download_with(source, save_path, None, CacheMode::default()).await
+    }
+
+    /// [`CargoRunner::download`] into `save_path`, or else the default config of `paths`,
+    /// using the [`PresetCache`](crate::PresetCache) of `paths` as `mode` says.
+    ///
+    /// [`CacheMode::Offline`] never goes online, URLs that were not downloaded
+    /// before fail with [`Error::NotCached`].
+    #[cfg(feature = "async")]
+    pub async fn download_with(
+        source: &str,
+        save_path: Option<PathBuf>,
+        paths: Option<&ConfigPaths>,
+        mode: CacheMode,
+    ) -> Result<Downloaded> {
+        let source: Source = source.parse()?;
+        let cache = || match paths {
+            Some(paths) => Ok(PresetCache::with_paths(paths)),
+            None => PresetCache::from_env(),
+        };
+        let (content, location) = match (&source.location, mode) {
+            (Location::Url(url), CacheMode::Revalidate) => {
+                let cache = cache()?;
+                let (cached, validators) = cache.lookup(url)?;
+                let fetched = fetch_if_modified_async(url, &validators).await?;
+                (cache.settle(url, cached, fetched)?, source.location.clone())
+            }
+            (Location::Url(_), CacheMode::Offline) => source.read(&cache()?)?,
+            _ => source.read_local()?,
+        };
+
+        Self::install(&source, location, &content, save_path, paths)
+    }
+
+    /// [`CargoRunner::download`] without an async runtime.
+    #[cfg(feature = "blocking")]
+    pub fn download_blocking(source: &str, save_path: Option<PathBuf>) -> Result<Downloaded> {
+        let cache = PresetCache::from_env()?;
+        let fetcher = cache.revalidating(HttpFetcher::default());
+        Self::download_with_fetcher(source, save_path, None, &fetcher)
+    }
+
+    /// [`CargoRunner::download_with`] fetching URLs through `fetcher`, available
+    /// without any of the download features.
+    ///
+    /// Nothing is cached unless `fetcher` does so, such as one returned by
+    /// [`PresetCache::revalidating`](crate::PresetCache::revalidating), or the
+    /// [`PresetCache`](crate::PresetCache) itself to only read presets cached earlier.
+    pub fn download_with_fetcher(
+        source: &str,
+        save_path: Option<PathBuf>,
+        paths: Option<&ConfigPaths>,
+        fetcher: &dyn Fetcher,
+    ) -> Result<Downloaded> {
+        let source: Source = source.parse()?;
+        let (content, location) = source.read(fetcher)?;
+        Self::install(&source, location, &content, save_path, paths)
+    }
+
+    /// Checks the config read from `source` and merges it into its destination.
+    fn install(
+        source: &Source,
+        location: Location,
+        content: &str,
+        save_path: Option<PathBuf>,
+        paths: Option<&ConfigPaths>,
+    ) -> Result<Downloaded> {
+        let sha256 = source.verify(content)?;
+        let config: CargoRunner =
+            content.parse().map_err(|e: Error| e.with_path(source.to_string()))?;
+
+        // Downloads into the default config are merged with the built-in configs
+        let (path, mut base, backup) = match (save_path, paths) {
+            (Some(path), _) => (path, CargoRunner(IndexMap::new()), false),
+            (None, Some(paths)) => (paths.config_file(), Self::default(), true),
+            (None, None) => (ConfigPaths::from_env()?.config_file(), Self::default(), true),
+        };
+
+        // Merge into the existing file in place so its comments and layout survive
+        let merged = if path.exists() {
+            // Loaded first, so saving fails on changes made after it that the backup misses
+            let mut document = ConfigDocument::load(&path)?;
+            if backup {
+                Self::create_backup(&path)?;
+            }
+            base = document.runner()?;
+            document.merge(config.clone())?;
+            document.save()?;
+            document.runner()?
+        } else {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| Error::io("failed to create directory", parent, e))?;
+            }
+            let mut document = ConfigDocument::from_runner(&base)?;
+            document.merge(config.clone())?;
+            document.save_to(&path)?;
+            document.runner()?
+        };
+
+        let downloaded = Downloaded {
+            source: Source {
+                location,
+                sha256: Some(sha256.clone()),
+            },
+            sha256,
+            path,
+        };
+        let locked = LockedPreset::new(source, &downloaded, &config, &base, &merged);
+        Lockfile::record(&Lockfile::path(&downloaded.path), vec![locked])?;
+        Ok(downloaded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Context;
+
+    #[test]
+    fn test_download_with_fetcher() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = ConfigPaths::new(dir.path());
+        let fetcher = |url: &str| match url {
+            "https://example.com/leptos.toml" => Ok(r#"
+                [run]
+                default = "leptos"
+
+                [[run.config]]
+                name = "leptos"
+                command_type = "subcommand"
+                command = "leptos"
+                sub_command = "watch"
+            "#
+            .to_string()),
+            _ => Err(Error::network(url, "404 Not Found")),
+        };
+
+        CargoRunner::download_with_fetcher(
+            "https://example.com/leptos.toml",
+            None,
+            Some(&paths),
+            &fetcher,
+        )
+        .unwrap();
+        let config = CargoRunner::load(paths.config_file()).unwrap();
+        assert_eq!(config.get_default(Context::Run), Some("leptos"));
+        assert!(config.find(Context::Build, "default").is_some());
+
+        let err = CargoRunner::download_with_fetcher(
+            "https://example.com/dx.toml",
+            None,
+            Some(&paths),
+            &fetcher,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "failed to download https://example.com/dx.toml: 404 Not Found"
+        );
+    }
+
+    #[test]
+    fn test_download_from_git_with_sha256() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("presets");
+        let git = |args: &[&str]| {
+            let output = std::process::Command::new("git")
+                .args(["-c", "user.name=t", "-c", "user.email=t@t", "-C"])
+                .arg(&repo)
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+            String::from_utf8(output.stdout).unwrap().trim().to_string()
+        };
+        let content = r#"
+            [run]
+            default = "dx"
+
+            [[run.config]]
+            name = "dx"
+            command_type = "subcommand"
+            command = "dx"
+            sub_command = "serve"
+        "#;
+        fs::create_dir_all(repo.join("dx")).unwrap();
+        fs::write(repo.join("dx/dx.toml"), content).unwrap();
+        git(&["init", "-q"]);
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "dx"]);
+        let commit = git(&["rev-parse", "HEAD"]);
+        let fetcher = |url: &str| Err(Error::network(url, "offline"));
+
+        let save_path = dir.path().join("dx.toml");
+        let digest = crate::source::sha256(content.as_bytes());
+        let spec = format!("git+file://{}#dx/dx.toml&sha256={}", repo.display(), digest);
+        let downloaded =
+            CargoRunner::download_with_fetcher(&spec, Some(save_path.clone()), None, &fetcher)
+                .unwrap();
+        assert_eq!(downloaded.sha256, digest);
+        assert_eq!(
+            downloaded.source.to_string(),
+            format!("git+file://{}#dx/dx.toml@{}&sha256={}", repo.display(), commit, digest)
+        );
+        let config = CargoRunner::load(save_path).unwrap();
+        assert_eq!(config.get_default(Context::Run), Some("dx"));
+
+        let wrong = format!("git+file://{}#dx/dx.toml&sha256={}", repo.display(), "0".repeat(64));
+        let other_path = dir.path().join("other.toml");
+        let err =
+            CargoRunner::download_with_fetcher(&wrong, Some(other_path.clone()), None, &fetcher)
+                .unwrap_err();
+        assert!(matches!(err, Error::ChecksumMismatch { .. }));
+        assert!(!other_path.exists());
+    }
+}
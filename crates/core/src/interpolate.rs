@@ -0,0 +1,441 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use indexmap::IndexMap;
+
+use crate::{
+    resolver::{find_ancestor, find_workspace_root, read_manifest},
+    CargoRunner, Config, Context, Error, Hook, Result,
+};
+
+/// Names accepted inside `${..}`, for error messages.
+const VARIABLES: &str =
+    "`env:NAME`, `workspaceRoot`, `packageRoot`, `file`, `package`, `bin`, `config:name.field`";
+
+/// Values substituted for `${..}` placeholders.
+///
+/// `$$` is a literal `$`; a `$` that does not start a placeholder is kept as is.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Variables {
+    pub workspace_root: Option<PathBuf>,
+    pub package_root: Option<PathBuf>,
+    pub file: Option<PathBuf>,
+    pub package: Option<String>,
+    pub bin: Option<String>,
+    /// Values for `${env:NAME}`, `None` to read the process environment.
+    pub env: Option<IndexMap<String, String>>,
+}
+
+impl Variables {
+    /// Variables for a source file, read from the nearest `Cargo.toml` and its workspace.
+    pub fn for_file(file: &Path) -> Result<Self> {
+        let file = fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+        let start = if file.is_dir() {
+            file.clone()
+        } else {
+            file.parent().map(Path::to_path_buf).unwrap_or_default()
+        };
+
+        let mut variables = Variables {
+            file: Some(file.clone()),
+            ..Default::default()
+        };
+
+        let Some(package_root) = find_ancestor(&start, |dir| dir.join("Cargo.toml").is_file())
+        else {
+            return Ok(variables);
+        };
+
+        let manifest = read_manifest(&package_root.join("Cargo.toml"))?;
+        variables.package = manifest
+            .get("package")
+            .and_then(|package| package.get("name"))
+            .and_then(toml::Value::as_str)
+            .map(str::to_string);
+        variables.bin = bin_name(&package_root, &file, variables.package.as_deref());
+        variables.workspace_root =
+            Some(find_workspace_root(&package_root)?.unwrap_or_else(|| package_root.clone()));
+        variables.package_root = Some(package_root);
+
+        Ok(variables)
+    }
+
+    /// Expands every placeholder in `input` except `${config:..}`, which needs a [`CargoRunner`].
+    pub fn expand(&self, input: &str) -> Result<String> {
+        expand(input, &mut |name| self.lookup(input, name))
+    }
+
+    fn lookup(&self, input: &str, name: &str) -> Result<String> {
+        let missing = |what: &str| Error::Interpolation {
+            input: input.to_string(),
+            message: format!("`${{{}}}` is not available {}", name, what),
+        };
+        let path = |path: &Option<PathBuf>, what: &str| {
+            path.as_ref()
+                .map(|path| path.display().to_string())
+                .ok_or_else(|| missing(what))
+        };
+
+        match name {
+            "workspaceRoot" => path(&self.workspace_root, "outside of a cargo project"),
+            "packageRoot" => path(&self.package_root, "outside of a cargo project"),
+            "file" => path(&self.file, "without a file"),
+            "package" => self
+                .package
+                .clone()
+                .ok_or_else(|| missing("outside of a cargo package")),
+            "bin" => self
+                .bin
+                .clone()
+                .ok_or_else(|| missing("for a file that is not a binary target")),
+            _ => match name.strip_prefix("env:") {
+                Some(key) => self.env_var(key).ok_or_else(|| Error::Interpolation {
+                    input: input.to_string(),
+                    message: format!("environment variable `{}` is not set", key),
+                }),
+                None => Err(Error::Interpolation {
+                    input: input.to_string(),
+                    message: format!("unknown variable `${{{}}}`, expected one of {}", name, VARIABLES),
+                }),
+            },
+        }
+    }
+
+    fn env_var(&self, key: &str) -> Option<String> {
+        match &self.env {
+            Some(env) => env.get(key).cloned(),
+            None => std::env::var(key).ok(),
+        }
+    }
+}
+
+/// The binary a file belongs to: `src/main.rs`, `src/bin/<name>.rs` or `src/bin/<name>/main.rs`.
+fn bin_name(package_root: &Path, file: &Path, package: Option<&str>) -> Option<String> {
+    let relative = file.strip_prefix(package_root.join("src")).ok()?;
+    let parts: Vec<_> = relative.iter().filter_map(|part| part.to_str()).collect();
+
+    match parts.as_slice() {
+        ["main.rs"] => package.map(str::to_string),
+        ["bin", file] => file.strip_suffix(".rs").map(str::to_string),
+        ["bin", name, "main.rs"] => Some(name.to_string()),
+        _ => None,
+    }
+}
+
+/// Replaces every `${name}` in `input` with `resolve(name)` and every `$$` with `$`.
+fn expand(input: &str, resolve: &mut dyn FnMut(&str) -> Result<String>) -> Result<String> {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(index) = rest.find('$') {
+        output.push_str(&rest[..index]);
+        rest = &rest[index..];
+
+        if let Some(after) = rest.strip_prefix("$$") {
+            output.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after.find('}').ok_or_else(|| Error::Interpolation {
+                input: input.to_string(),
+                message: "unterminated `${`, write `$$` for a literal `$`".to_string(),
+            })?;
+            output.push_str(&resolve(&after[..end])?);
+            rest = &after[end + 1..];
+        } else {
+            output.push('$');
+            rest = &rest[1..];
+        }
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+impl CargoRunner {
+    /// The config `name` of `context` with every placeholder in its string values,
+    /// the items of its lists, its `env` values and its hooks expanded. `env_remove`
+    /// holds variable names and is left as is.
+    ///
+    /// `${config:name.field}` reads a field of another config in the same context,
+    /// `${config:context.name.field}` one in any context; `field` may be `env.KEY`.
+    pub fn expand(&self, context: Context, name: &str, variables: &Variables) -> Result<Config> {
+        let key = context.as_str();
+        let config = self.config_in(key, name)?;
+
+        let mut expander = Expander {
+            runner: self,
+            variables,
+            stack: Vec::new(),
+        };
+
+        let mut expanded = config.clone();
+        for field in ["command", "sub_command", "toolchain", "profile", "target", "cwd"] {
+            if let Some(value) = field_mut(&mut expanded, field) {
+                *value = expander.expand(key, name, field, value)?;
+            }
+        }
+        for field in ["features", "args", "extra_args", "path_prepend"] {
+            if let Some(values) = list_mut(&mut expanded, field) {
+                for (index, value) in values.iter_mut().enumerate() {
+                    *value = expander.expand(key, name, &format!("{}[{}]", field, index), value)?;
+                }
+            }
+        }
+        if let Some(env) = expanded.env.as_mut() {
+            for (env_key, value) in env.iter_mut() {
+                *value = expander.expand(key, name, &format!("env.{}", env_key), value)?;
+            }
+        }
+        let hooks = [("pre", expanded.pre.as_mut()), ("post", expanded.post.as_mut())];
+        for (field, hooks) in hooks {
+            for (index, hook) in hooks.into_iter().flatten().enumerate() {
+                let location = format!("{}[{}]", field, index);
+                expander.expand_hook(key, name, &location, hook)?;
+            }
+        }
+
+        Ok(expanded)
+    }
+
+    fn config_in(&self, context: &str, name: &str) -> Result<&Config> {
+        self.0
+            .get(context)
+            .and_then(|(_, configs)| configs.as_ref())
+            .and_then(|configs| configs.iter().find(|c| c.name == name))
+            .ok_or_else(|| Error::UnknownConfig {
+                context: context.to_string(),
+                name: name.to_string(),
+            })
+    }
+}
+
+fn field_mut<'a>(config: &'a mut Config, field: &str) -> Option<&'a mut String> {
+    match field {
+        "command" => config.command.as_mut(),
+        "sub_command" => config.sub_command.as_mut(),
+        "toolchain" => config.toolchain.as_mut(),
+        "profile" => config.profile.as_mut(),
+        "target" => config.target.as_mut(),
+        "cwd" => config.cwd.as_mut(),
+        _ => None,
+    }
+}
+
+fn list_mut<'a>(config: &'a mut Config, field: &str) -> Option<&'a mut Vec<String>> {
+    match field {
+        "features" => config.features.as_mut(),
+        "args" => config.args.as_mut(),
+        "extra_args" => config.extra_args.as_mut(),
+        "path_prepend" => config.path_prepend.as_mut(),
+        _ => None,
+    }
+}
+
+struct Expander<'a> {
+    runner: &'a CargoRunner,
+    variables: &'a Variables,
+    /// `context.name.field` of every value being expanded, to detect cycles.
+    stack: Vec<String>,
+}
+
+impl Expander<'_> {
+    fn expand(&mut self, context: &str, name: &str, field: &str, value: &str) -> Result<String> {
+        let location = format!("{}.{}.{}", context, name, field);
+        if self.stack.contains(&location) {
+            self.stack.push(location);
+            return Err(Error::Interpolation {
+                input: value.to_string(),
+                message: format!("`${{config:..}}` cycle: {}", self.stack.join(" -> ")),
+            });
+        }
+
+        self.stack.push(location);
+        let expanded = expand(value, &mut |variable| match variable.strip_prefix("config:") {
+            Some(reference) => self.reference(context, value, reference),
+            None => self.variables.lookup(value, variable),
+        });
+        self.stack.pop();
+        expanded
+    }
+
+    fn expand_hook(
+        &mut self,
+        context: &str,
+        name: &str,
+        location: &str,
+        hook: &mut Hook,
+    ) -> Result<()> {
+        let field = |field: &str| format!("{}.{}", location, field);
+
+        hook.command = self.expand(context, name, &field("command"), &hook.command)?;
+        for (index, arg) in hook.args.iter_mut().flatten().enumerate() {
+            *arg = self.expand(context, name, &field(&format!("args[{}]", index)), arg)?;
+        }
+        if let Some(cwd) = hook.cwd.as_mut() {
+            *cwd = self.expand(context, name, &field("cwd"), cwd)?;
+        }
+        for (env_key, value) in hook.env.iter_mut().flatten() {
+            *value = self.expand(context, name, &field(&format!("env.{}", env_key)), value)?;
+        }
+        Ok(())
+    }
+
+    /// Expands `${config:reference}` found in `input`, a value of a config in `context`.
+    fn reference(&mut self, context: &str, input: &str, reference: &str) -> Result<String> {
+        let invalid = |message: String| Error::Interpolation {
+            input: input.to_string(),
+            message,
+        };
+
+        // `context.name.field` when it names an existing config, `name.field` otherwise
+        let (context, rest) = match reference.split_once('.') {
+            Some((first, rest))
+                if first != context
+                    && rest
+                        .split_once('.')
+                        .is_some_and(|(name, _)| self.runner.config_in(first, name).is_ok()) =>
+            {
+                (first.to_string(), rest)
+            }
+            _ => (context.to_string(), reference),
+        };
+        let (name, field) = rest.split_once('.').ok_or_else(|| {
+            invalid(format!(
+                "`${{config:{}}}` must name a field, e.g. `${{config:{}.command}}`",
+                reference, rest
+            ))
+        })?;
+
+        let config = self.runner.config_in(&context, name)?;
+        let value = field_value(config, field).ok_or_else(|| {
+            invalid(format!(
+                "`${{config:{}}}` does not name a value of `{}.{}`",
+                reference, context, name
+            ))
+        })?;
+
+        self.expand(&context, name, field, &value)
+    }
+}
+
+/// The value at a dotted `field` path of `config`, e.g. `command` or `env.RUST_LOG`.
+fn field_value(config: &Config, field: &str) -> Option<String> {
+    let mut value = toml::Value::try_from(config).ok()?;
+    for key in field.split('.') {
+        value = value.as_table_mut()?.remove(key)?;
+    }
+
+    match value {
+        toml::Value::String(value) => Some(value),
+        toml::Value::Integer(value) => Some(value.to_string()),
+        toml::Value::Boolean(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> Variables {
+        Variables {
+            workspace_root: Some(PathBuf::from("/work")),
+            package_root: Some(PathBuf::from("/work/app")),
+            file: Some(PathBuf::from("/work/app/src/main.rs")),
+            package: Some("app".to_string()),
+            bin: Some("app".to_string()),
+            env: Some(IndexMap::from([("HOME".to_string(), "/home/me".to_string())])),
+        }
+    }
+
+    #[test]
+    fn test_expand_variables() {
+        let variables = variables();
+
+        assert_eq!(
+            variables
+                .expand("${packageRoot}/target ${env:HOME} $$HOME $PATH ${bin}")
+                .unwrap(),
+            "/work/app/target /home/me $HOME $PATH app"
+        );
+
+        let messages: Vec<_> = ["${nope}", "${env:MISSING}", "${file", "${config:a.b}"]
+            .into_iter()
+            .map(|input| variables.expand(input).unwrap_err().to_string())
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                format!("failed to expand `${{nope}}`: unknown variable `${{nope}}`, expected one of {}", VARIABLES),
+                "failed to expand `${env:MISSING}`: environment variable `MISSING` is not set".to_string(),
+                "failed to expand `${file`: unterminated `${`, write `$$` for a literal `$`".to_string(),
+                format!("failed to expand `${{config:a.b}}`: unknown variable `${{config:a.b}}`, expected one of {}", VARIABLES),
+            ]
+        );
+
+        let empty = Variables::default().expand("${workspaceRoot}").unwrap_err();
+        assert!(empty.to_string().contains("outside of a cargo project"));
+    }
+
+    #[test]
+    fn test_expand_config_references() {
+        let runner: CargoRunner = r#"
+            [[run.config]]
+            name = "leptos"
+            command = "leptos"
+            sub_command = "${config:default.sub_command}"
+            env = { LEPTOS_OUTPUT_NAME = "${package}", TARGET = "${config:build.release.env.DIR}/${bin}" }
+
+            [[run.config]]
+            name = "default"
+            sub_command = "watch"
+
+            [[run.config]]
+            name = "loop"
+            command = "${config:loop.sub_command}"
+            sub_command = "${config:loop.command}"
+
+            [[build.config]]
+            name = "release"
+            env = { DIR = "${workspaceRoot}/dist" }
+        "#
+        .parse()
+        .unwrap();
+
+        let config = runner.expand(Context::Run, "leptos", &variables()).unwrap();
+        assert_eq!(config.sub_command.as_deref(), Some("watch"));
+        let env = config.env.unwrap();
+        assert_eq!(env["LEPTOS_OUTPUT_NAME"], "app");
+        assert_eq!(env["TARGET"], "/work/dist/app");
+
+        let err = runner.expand(Context::Run, "loop", &variables()).unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("cycle: run.loop.command -> run.loop.sub_command -> run.loop.command"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_variables_for_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let package = root.join("crates/tool");
+        let file = package.join("src/bin/fmt.rs");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(root.join("Cargo.toml"), "[workspace]\nmembers = [\"crates/*\"]\n").unwrap();
+        fs::write(package.join("Cargo.toml"), "[package]\nname = \"tool\"\n").unwrap();
+        fs::write(&file, "fn main() {}\n").unwrap();
+
+        let variables = Variables::for_file(&file).unwrap();
+
+        assert_eq!(variables.workspace_root, Some(root));
+        assert_eq!(variables.package_root, Some(package));
+        assert_eq!(variables.package.as_deref(), Some("tool"));
+        assert_eq!(variables.bin.as_deref(), Some("fmt"));
+        assert_eq!(variables.file, Some(file));
+    }
+}
@@ -1,3 +1,37 @@
+mod backup;
+mod cache;
+mod document;
+mod edit;
 mod entities;
+mod error;
+mod fetch;
+mod interpolate;
+mod lockfile;
+mod merge;
+mod migration;
+mod paths;
+mod plan;
+mod provenance;
+mod resolver;
+mod source;
+mod validator;
+mod writer;
 
+pub use backup::{Backup, DiffLine, RetentionPolicy};
+pub use cache::{CacheMode, CachedPreset, PresetCache, Revalidating};
+pub use document::ConfigDocument;
+pub use edit::Change;
 pub use entities::*;
+pub use error::{Error, Result};
+#[cfg(feature = "blocking")]
+pub use fetch::HttpFetcher;
+pub use fetch::{Fetched, Fetcher, Validators};
+pub use interpolate::Variables;
+pub use lockfile::{LockedPreset, Lockfile, PresetUpdate, UpdateReport, LOCK_FILE_NAME};
+pub use merge::{FieldPath, MergeOptions, MergeReport, MergeStrategy};
+pub use migration::{MigrationReport, CONFIG_VERSION};
+pub use paths::{ConfigPaths, HOME_ENV};
+pub use plan::{CommandPlan, HookPlan};
+pub use provenance::{Assignment, Explanation, Origin, Provenance, Trace};
+pub use resolver::{Layer, Resolved, Resolver, CONFIG_FILE_NAME};
+pub use source::{Downloaded, Location, Source};
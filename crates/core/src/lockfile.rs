@@ -0,0 +1,585 @@
+use std::{
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "blocking")]
+use crate::{HttpFetcher, PresetCache};
+use crate::{
+    backup::diff_lines,
+    source::sha256,
+    writer::{self, FileLock},
+    CargoRunner, Config, ConfigDocument, DiffLine, Downloaded, Error, Fetcher, Result, Source,
+};
+
+/// Name of the lockfile kept next to the configs presets are downloaded into.
+pub const LOCK_FILE_NAME: &str = "cargo-runner.lock";
+
+const HEADER: &str = "# Generated by cargo-runner, do not edit by hand.
+# Records where the presets downloaded into the configs next to it came from.
+
+";
+
+/// The presets downloaded into the configs of a directory, kept in [`LOCK_FILE_NAME`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    #[serde(default, rename = "preset", skip_serializing_if = "Vec::is_empty")]
+    pub presets: Vec<LockedPreset>,
+}
+
+/// A preset merged into a config by [`CargoRunner::download`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedPreset {
+    /// The source as it was given, what [`CargoRunner::update_with_fetcher`] fetches again.
+    pub source: String,
+    /// The source pinned to what was fetched, see [`Downloaded::source`].
+    pub resolved: String,
+    pub sha256: String,
+    /// When the preset was fetched, in seconds since the Unix epoch.
+    pub fetched_at: u64,
+    /// File name of the config the preset was merged into.
+    pub config: String,
+    /// The configs the preset contributed, as `context.name`, each with the sha256
+    /// it had once merged so local edits can be told apart.
+    #[serde(default)]
+    pub configs: IndexMap<String, String>,
+    /// The configs the preset was merged onto, as they were before, so updates can
+    /// merge the new version onto them. Configs the preset added have none.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub bases: IndexMap<String, Config>,
+}
+
+/// What [`CargoRunner::update_with_fetcher`] changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpdateReport {
+    pub presets: Vec<PresetUpdate>,
+    /// How the config file changed, empty when it was left as it was.
+    pub diff: Vec<DiffLine>,
+}
+
+/// How a single preset was updated, configs named as `context.name`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PresetUpdate {
+    pub source: String,
+    pub previous: String,
+    pub sha256: String,
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+    /// Configs changed or removed locally since they were merged, left as they are.
+    pub kept: Vec<String>,
+}
+
+impl Lockfile {
+    /// The lockfile of the config at `config_path`, in the same directory.
+    pub fn path(config_path: &Path) -> PathBuf {
+        config_path.with_file_name(LOCK_FILE_NAME)
+    }
+
+    /// Reads the lockfile at `path`, empty if there is none.
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                toml::from_str(&content).map_err(|e| Error::parse(Some(path.to_path_buf()), e))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Lockfile::default()),
+            Err(e) => Err(Error::io("failed to read", path, e)),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        writer::write(path, self.to_toml()?.as_bytes(), None)?;
+        Ok(())
+    }
+
+    /// The presets merged into the config at `config_path`.
+    pub fn presets_for<'a>(
+        &'a self,
+        config_path: &Path,
+    ) -> impl Iterator<Item = &'a LockedPreset> + 'a {
+        let config = file_name(config_path);
+        self.presets.iter().filter(move |preset| preset.config == config)
+    }
+
+    /// Adds `preset`, replacing the one with the same source and config.
+    pub fn insert(&mut self, preset: LockedPreset) {
+        match self
+            .presets
+            .iter_mut()
+            .find(|p| p.source == preset.source && p.config == preset.config)
+        {
+            Some(existing) => *existing = preset,
+            None => self.presets.push(preset),
+        }
+    }
+
+    /// The configs of `runner`, read from `config_path`, that are still as a preset
+    /// merged them, as `(context, name)` along with that preset.
+    pub(crate) fn downloaded<'a>(
+        &'a self,
+        config_path: &Path,
+        runner: &CargoRunner,
+    ) -> Vec<(String, String, &'a LockedPreset)> {
+        let mut downloaded = Vec::new();
+        for preset in self.presets_for(config_path) {
+            for (key, recorded) in &preset.configs {
+                let Some((context, name)) = key.split_once('.') else {
+                    continue;
+                };
+                if find(runner, context, name).is_some_and(|config| digest(config) == *recorded) {
+                    downloaded.push((context.to_string(), name.to_string(), preset));
+                }
+            }
+        }
+        downloaded
+    }
+
+    /// Adds `presets` to the lockfile at `path` while holding its lock.
+    pub(crate) fn record(path: &Path, presets: Vec<LockedPreset>) -> Result<()> {
+        let lock = FileLock::acquire(path)?;
+        let mut lockfile = Lockfile::load(path)?;
+        for preset in presets {
+            lockfile.insert(preset);
+        }
+        lock.write(lockfile.to_toml()?.as_bytes(), None)?;
+        Ok(())
+    }
+
+    fn to_toml(&self) -> Result<String> {
+        Ok(format!("{}{}", HEADER, toml::to_string(self)?))
+    }
+}
+
+impl LockedPreset {
+    /// The entry for `preset`, downloaded as `downloaded` and merged onto `base`,
+    /// giving `merged`.
+    pub(crate) fn new(
+        source: &Source,
+        downloaded: &Downloaded,
+        preset: &CargoRunner,
+        base: &CargoRunner,
+        merged: &CargoRunner,
+    ) -> Self {
+        let bases = configs(preset)
+            .filter_map(|(key, context, config)| {
+                Some((key, find(base, context, &config.name)?.clone()))
+            })
+            .collect();
+        let configs = configs(preset)
+            .filter_map(|(key, context, config)| {
+                let merged = find(merged, context, &config.name)?;
+                Some((key, digest(merged)))
+            })
+            .collect();
+
+        LockedPreset {
+            source: source.to_string(),
+            resolved: downloaded.source.to_string(),
+            sha256: downloaded.sha256.clone(),
+            fetched_at: now(),
+            config: file_name(&downloaded.path),
+            configs,
+            bases,
+        }
+    }
+}
+
+impl PresetUpdate {
+    pub fn is_unchanged(&self) -> bool {
+        self.previous == self.sha256
+    }
+}
+
+impl Display for PresetUpdate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_unchanged() {
+            return writeln!(f, "{}: up to date", self.source);
+        }
+
+        writeln!(
+            f,
+            "{}: sha256 {} -> {}",
+            self.source,
+            &self.previous[..12.min(self.previous.len())],
+            &self.sha256[..12]
+        )?;
+        for (action, keys) in [
+            ("added", &self.added),
+            ("updated", &self.updated),
+            ("removed", &self.removed),
+        ] {
+            for key in keys {
+                writeln!(f, "  {} {}", action, key)?;
+            }
+        }
+        for key in &self.kept {
+            writeln!(f, "  kept {}, edited locally", key)?;
+        }
+        Ok(())
+    }
+}
+
+impl CargoRunner {
+    /// [`CargoRunner::update_with_fetcher`] over HTTP(S) with a blocking client,
+    /// revalidating the copies in the [`PresetCache`].
+    #[cfg(feature = "blocking")]
+    pub fn update_blocking(config_path: &Path) -> Result<UpdateReport> {
+        let cache = PresetCache::from_env()?;
+        Self::update_with_fetcher(config_path, &cache.revalidating(HttpFetcher::default()))
+    }
+
+    /// Fetches the presets recorded in the lockfile of `config_path` again and
+    /// merges those that changed back into it, fetching URLs through `fetcher`.
+    ///
+    /// Only configs a preset contributed are touched, and of those only the ones
+    /// still as they were merged: configs edited or removed since are kept. A
+    /// config the preset was merged onto gets the new version merged onto it as it
+    /// was before, so fields the preset does not set are kept. The config is backed
+    /// up before it is changed, and left alone if any updated config has a
+    /// `sub_command` outside of its `allowed_subcommands`.
+    pub fn update_with_fetcher(config_path: &Path, fetcher: &dyn Fetcher) -> Result<UpdateReport> {
+        let lock_path = Lockfile::path(config_path);
+        let lockfile = Lockfile::load(&lock_path)?;
+        let mut document = ConfigDocument::load(config_path)?;
+        let before = document.to_string();
+        let mut runner = document.runner()?;
+
+        let mut report = UpdateReport::default();
+        let mut locked = Vec::new();
+        for preset in lockfile.presets_for(config_path) {
+            let source: Source = preset.source.parse()?;
+            let (content, location) = source.read(fetcher)?;
+            let sha256 = source.verify(&content)?;
+            if sha256 == preset.sha256 {
+                report.presets.push(PresetUpdate {
+                    source: preset.source.clone(),
+                    previous: preset.sha256.clone(),
+                    sha256,
+                    ..Default::default()
+                });
+                continue;
+            }
+
+            let fetched: CargoRunner =
+                content.parse().map_err(|e: Error| e.with_path(preset.source.clone()))?;
+            // Check every config before any is applied, so nothing is half updated
+            for (_, _, config) in configs(&fetched) {
+                config.check_sub_command()?;
+            }
+            let (update, configs) = update_preset(&mut runner, preset, &fetched, sha256)?;
+            let bases = preset
+                .bases
+                .iter()
+                .filter(|(key, _)| configs.contains_key(*key))
+                .map(|(key, base)| (key.clone(), base.clone()))
+                .collect();
+            locked.push(LockedPreset {
+                source: preset.source.clone(),
+                resolved: Source {
+                    location,
+                    sha256: Some(update.sha256.clone()),
+                }
+                .to_string(),
+                sha256: update.sha256.clone(),
+                fetched_at: now(),
+                config: preset.config.clone(),
+                configs,
+                bases,
+            });
+            report.presets.push(update);
+        }
+
+        document.apply(&runner)?;
+        let after = document.to_string();
+        if after != before {
+            Self::create_backup(config_path)?;
+            document.save()?;
+            report.diff = diff_lines(&before, &after);
+        }
+        if !locked.is_empty() {
+            Lockfile::record(&lock_path, locked)?;
+        }
+        Ok(report)
+    }
+}
+
+/// Applies the `fetched` version of `preset` to `runner`, returning what changed
+/// and the configs to record for it.
+fn update_preset(
+    runner: &mut CargoRunner,
+    preset: &LockedPreset,
+    fetched: &CargoRunner,
+    sha256: String,
+) -> Result<(PresetUpdate, IndexMap<String, String>)> {
+    let mut update = PresetUpdate {
+        source: preset.source.clone(),
+        previous: preset.sha256.clone(),
+        sha256,
+        ..Default::default()
+    };
+    let mut locked = IndexMap::new();
+
+    for (key, context, config) in configs(fetched) {
+        // What merging the new version would have given in the first place
+        let merged = match preset.bases.get(&key) {
+            Some(base) => {
+                let mut base = base.clone();
+                base.merge(config)?;
+                base
+            }
+            None => config.clone(),
+        };
+        let current = find(runner, context, &config.name).cloned();
+        let recorded = preset.configs.get(&key);
+        match (current, recorded) {
+            (None, None) => {
+                runner
+                    .0
+                    .entry(context.to_string())
+                    .or_default()
+                    .1
+                    .get_or_insert_with(Vec::new)
+                    .push(merged.clone());
+                update.added.push(key.clone());
+                locked.insert(key, digest(&merged));
+            }
+            (Some(current), _) if current == merged => {
+                locked.insert(key, digest(&merged));
+            }
+            (Some(current), Some(recorded)) if digest(&current) == *recorded => {
+                if let Some(existing) = find_mut(runner, context, &config.name) {
+                    *existing = merged.clone();
+                }
+                update.updated.push(key.clone());
+                locked.insert(key, digest(&merged));
+            }
+            // Edited, or removed, since it was merged
+            (_, recorded) => {
+                update.kept.push(key.clone());
+                if let Some(recorded) = recorded {
+                    locked.insert(key, recorded.clone());
+                }
+            }
+        }
+    }
+
+    // Configs the preset no longer has
+    for (key, recorded) in &preset.configs {
+        if locked.contains_key(key) || update.kept.contains(key) {
+            continue;
+        }
+        let Some((context, name)) = key.split_once('.') else {
+            continue;
+        };
+        let Some(current) = find(runner, context, name) else {
+            continue;
+        };
+        let is_default = runner.0.get(context).and_then(|(default, _)| default.as_deref());
+        if digest(current) != *recorded {
+            update.kept.push(key.clone());
+        } else if let Some(base) = preset.bases.get(key) {
+            // Merged onto a config of its own, which goes back to how it was
+            if let Some(existing) = find_mut(runner, context, name) {
+                *existing = base.clone();
+            }
+            update.updated.push(key.clone());
+        } else if is_default != Some(name) {
+            if let Some((_, Some(configs))) = runner.0.get_mut(context) {
+                configs.retain(|config| config.name != name);
+            }
+            update.removed.push(key.clone());
+        } else {
+            update.kept.push(key.clone());
+        }
+    }
+
+    // Defaults of the preset only fill in contexts that have none
+    for (context, (default, _)) in &fetched.0 {
+        let Some(default) = default else {
+            continue;
+        };
+        let exists = find(runner, context, default).is_some();
+        if let Some((current @ None, _)) = runner.0.get_mut(context) {
+            if exists {
+                *current = Some(default.clone());
+            }
+        }
+    }
+
+    Ok((update, locked))
+}
+
+/// Every config of `runner` along with its `context.name` key and its context.
+fn configs(runner: &CargoRunner) -> impl Iterator<Item = (String, &str, &Config)> {
+    runner.0.iter().flat_map(|(context, (_, configs))| {
+        configs.iter().flatten().map(move |config| {
+            (format!("{}.{}", context, config.name), context.as_str(), config)
+        })
+    })
+}
+
+fn find<'a>(runner: &'a CargoRunner, context: &str, name: &str) -> Option<&'a Config> {
+    runner.0.get(context)?.1.as_ref()?.iter().find(|config| config.name == name)
+}
+
+fn find_mut<'a>(runner: &'a mut CargoRunner, context: &str, name: &str) -> Option<&'a mut Config> {
+    runner.0.get_mut(context)?.1.as_mut()?.iter_mut().find(|config| config.name == name)
+}
+
+fn digest(config: &Config) -> String {
+    sha256(toml::to_string(config).expect("configs always serialize to TOML").as_bytes())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::Context;
+
+    const URL: &str = "https://example.com/leptos.toml";
+
+    fn preset(watch: &str, extra: &str) -> String {
+        format!(
+            r#"
+            [run]
+            default = "leptos"
+
+            [[run.config]]
+            name = "leptos"
+            command_type = "subcommand"
+            command = "leptos"
+            sub_command = "{}"
+
+            [[run.config]]
+            name = "trunk"
+            command_type = "shell"
+            command = "trunk"
+            sub_command = "serve"
+            {}
+            "#,
+            watch, extra
+        )
+    }
+
+    #[test]
+    fn test_update_keeps_local_edits() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cargo-runner.toml");
+        let remote = RefCell::new(preset("watch", ""));
+        let fetcher = |_: &str| Ok(remote.borrow().clone());
+
+        let downloaded =
+            CargoRunner::download_with_fetcher(URL, Some(path.clone()), None, &fetcher).unwrap();
+        let lockfile = Lockfile::load(&Lockfile::path(&path)).unwrap();
+        let locked: Vec<_> = lockfile.presets_for(&path).collect();
+        assert_eq!(locked.len(), 1);
+        assert_eq!(locked[0].source, URL);
+        assert_eq!(locked[0].sha256, downloaded.sha256);
+        assert_eq!(locked[0].config, "cargo-runner.toml");
+        assert_eq!(locked[0].configs.keys().collect::<Vec<_>>(), ["run.leptos", "run.trunk"]);
+
+        let report = CargoRunner::update_with_fetcher(&path, &fetcher).unwrap();
+        assert!(report.presets[0].is_unchanged());
+        assert!(report.diff.is_empty());
+
+        // Edit trunk locally, then publish a new version changing both configs
+        let mut document = ConfigDocument::load(&path).unwrap();
+        let mut trunk = document.runner().unwrap().find(Context::Run, "trunk").cloned().unwrap();
+        trunk.sub_command = Some("build".to_string());
+        document.add_config(Context::Run, &trunk).unwrap();
+        document.save().unwrap();
+        *remote.borrow_mut() = preset("serve", "\n[[build.config]]\nname = \"leptos\"");
+
+        let report = CargoRunner::update_with_fetcher(&path, &fetcher).unwrap();
+        let update = &report.presets[0];
+        assert_eq!(update.updated, ["run.leptos"]);
+        assert_eq!(update.added, ["build.leptos"]);
+        assert_eq!(update.kept, ["run.trunk"]);
+        assert!(report.diff.contains(&DiffLine::Added("sub_command = \"serve\"".to_string())));
+
+        let config = CargoRunner::load(path.clone()).unwrap();
+        let sub_command = |name| config.find(Context::Run, name).unwrap().sub_command.clone();
+        assert_eq!(sub_command("leptos").as_deref(), Some("serve"));
+        assert_eq!(sub_command("trunk").as_deref(), Some("build"));
+        assert!(config.find(Context::Build, "leptos").is_some());
+
+        let lockfile = Lockfile::load(&Lockfile::path(&path)).unwrap();
+        assert_eq!(lockfile.presets.len(), 1);
+        assert_eq!(lockfile.presets[0].sha256, update.sha256);
+        assert_eq!(CargoRunner::list_backups(&path).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_update_keeps_fields_the_preset_does_not_set() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cargo-runner.toml");
+        fs::write(
+            &path,
+            "[[run.config]]\nname = \"default\"\ncommand = \"cargo\"\nsub_command = \"run\"\n",
+        )
+        .unwrap();
+        let env = |value: &str| {
+            format!("[[run.config]]\nname = \"default\"\nenv = {{ RUST_LOG = \"{}\" }}\n", value)
+        };
+        let remote = RefCell::new(env("info"));
+        let fetcher = |_: &str| Ok(remote.borrow().clone());
+        CargoRunner::download_with_fetcher(URL, Some(path.clone()), None, &fetcher).unwrap();
+
+        *remote.borrow_mut() = env("debug");
+        let report = CargoRunner::update_with_fetcher(&path, &fetcher).unwrap();
+        assert_eq!(report.presets[0].updated, ["run.default"]);
+        let config = CargoRunner::load(path.clone()).unwrap();
+        let default = config.find(Context::Run, "default").unwrap();
+        assert_eq!(default.command.as_deref(), Some("cargo"));
+        assert_eq!(default.sub_command.as_deref(), Some("run"));
+        assert_eq!(default.env.as_ref().unwrap()["RUST_LOG"], "debug");
+
+        // A version without the config puts it back as it was before the download
+        *remote.borrow_mut() = "[[run.config]]\nname = \"other\"\ncommand = \"other\"\n".into();
+        let report = CargoRunner::update_with_fetcher(&path, &fetcher).unwrap();
+        assert_eq!(report.presets[0].updated, ["run.default"]);
+        let config = CargoRunner::load(path).unwrap();
+        let default = config.find(Context::Run, "default").unwrap();
+        assert_eq!(default.sub_command.as_deref(), Some("run"));
+        assert_eq!(default.env, None);
+    }
+
+    #[test]
+    fn test_update_rejects_disallowed_sub_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cargo-runner.toml");
+        let remote = RefCell::new(preset("watch", ""));
+        let fetcher = |_: &str| Ok(remote.borrow().clone());
+        CargoRunner::download_with_fetcher(URL, Some(path.clone()), None, &fetcher).unwrap();
+        let before = fs::read_to_string(&path).unwrap();
+        let lock_before = fs::read_to_string(Lockfile::path(&path)).unwrap();
+
+        *remote.borrow_mut() = preset("serve", "allowed_subcommands = [\"build\"]");
+        let err = CargoRunner::update_with_fetcher(&path, &fetcher).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::SubCommandNotAllowed { ref name, ref sub_command, .. }
+                if name == "trunk" && sub_command == "serve"
+        ));
+        assert_eq!(fs::read_to_string(&path).unwrap(), before);
+        assert_eq!(fs::read_to_string(Lockfile::path(&path)).unwrap(), lock_before);
+        assert!(CargoRunner::list_backups(&path).unwrap().is_empty());
+    }
+}
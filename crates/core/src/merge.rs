@@ -0,0 +1,372 @@
+use std::fmt::Display;
+
+use indexmap::IndexMap;
+
+use crate::{CargoRunner, Error, Result};
+
+/// How a field set on both sides of a merge is combined.
+///
+/// Strategies apply to whole fields: with `KeepBase`, an `env` table set on both
+/// sides keeps the base table as it is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// What [`CargoRunner::merge`] does: lists are extended, `env` keys are added
+    /// or replaced, hooks are merged by name and every other field is replaced.
+    #[default]
+    Extend,
+    /// The merged-in value replaces the base one, lists and `env` included.
+    Override,
+    /// The base value is kept and the field is listed in [`MergeReport::conflicts`].
+    KeepBase,
+    /// The merge fails with [`Error::MergeConflict`] when the two values differ.
+    ErrorOnConflict,
+}
+
+/// Strategies used by [`CargoRunner::merge_with`], per field or for all of them.
+///
+/// Fields are named as in the config file, e.g. `env` or `sub_command`; `default`
+/// stands for the default config of each context.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeOptions {
+    /// Used for every field without an entry in `fields`.
+    pub strategy: MergeStrategy,
+    pub fields: IndexMap<String, MergeStrategy>,
+}
+
+impl MergeOptions {
+    /// Options using `strategy` for every field.
+    pub fn new(strategy: MergeStrategy) -> Self {
+        MergeOptions {
+            strategy,
+            fields: IndexMap::new(),
+        }
+    }
+
+    /// Uses `strategy` for `field` only.
+    pub fn field(mut self, field: &str, strategy: MergeStrategy) -> Self {
+        self.fields.insert(field.to_string(), strategy);
+        self
+    }
+
+    /// The strategy used for `field`.
+    pub fn strategy_for(&self, field: &str) -> MergeStrategy {
+        self.fields.get(field).copied().unwrap_or(self.strategy)
+    }
+}
+
+/// A field of a context or of one of its configs, e.g. `run.leptos.sub_command`
+/// or `run.default`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldPath {
+    pub context: String,
+    /// The config the field belongs to, `None` for the context's `default`.
+    pub config: Option<String>,
+    pub field: String,
+}
+
+impl Display for FieldPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.config {
+            Some(config) => write!(f, "{}.{}.{}", self.context, config, self.field),
+            None => write!(f, "{}.{}", self.context, self.field),
+        }
+    }
+}
+
+/// What [`CargoRunner::merge_with`] changed, and what it left alone.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// Configs that were not in the base, as `(context, name)`.
+    pub added: Vec<(String, String)>,
+    /// Fields whose base value was replaced by a different one.
+    pub overridden: Vec<FieldPath>,
+    /// Fields set differently on both sides where the base value was kept.
+    pub conflicts: Vec<FieldPath>,
+    /// Defaults that were ignored because their context has no such config,
+    /// as `(context, default)`.
+    pub rejected_defaults: Vec<(String, String)>,
+}
+
+impl MergeReport {
+    /// Whether the merge neither added nor replaced anything and nothing was left out.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.overridden.is_empty()
+            && self.conflicts.is_empty()
+            && self.rejected_defaults.is_empty()
+    }
+
+    fn record(&mut self, path: FieldPath, outcome: FieldOutcome, conflicts: &mut Vec<String>) {
+        match outcome {
+            FieldOutcome::Overridden => self.overridden.push(path),
+            FieldOutcome::KeptBase => self.conflicts.push(path),
+            FieldOutcome::Conflict => conflicts.push(path.to_string()),
+        }
+    }
+}
+
+impl Display for MergeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut lines = Vec::new();
+        for (context, name) in &self.added {
+            lines.push(format!("added config '{}' to '{}'", name, context));
+        }
+        for path in &self.overridden {
+            lines.push(format!("overrode `{}`", path));
+        }
+        for path in &self.conflicts {
+            lines.push(format!("kept `{}`", path));
+        }
+        for (context, default) in &self.rejected_defaults {
+            lines.push(format!(
+                "ignored default '{}' of '{}', no such config",
+                default, context
+            ));
+        }
+        if lines.is_empty() {
+            return write!(f, "nothing to merge");
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// What happened to a field set on both sides with different values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FieldOutcome {
+    Overridden,
+    KeptBase,
+    Conflict,
+}
+
+/// Merges fields one at a time with the strategies of `options`, remembering the
+/// fields that were overridden or conflicted.
+pub(crate) struct FieldMerger<'a> {
+    options: &'a MergeOptions,
+    pub(crate) outcomes: Vec<(&'static str, FieldOutcome)>,
+}
+
+impl<'a> FieldMerger<'a> {
+    pub(crate) fn new(options: &'a MergeOptions) -> Self {
+        FieldMerger {
+            options,
+            outcomes: Vec::new(),
+        }
+    }
+
+    /// Merges `other` into `base`, extending with `extend` for fields that have an
+    /// extend behavior other than replacing.
+    ///
+    /// Returns whether the value of `other` was used.
+    pub(crate) fn field<T: Clone + PartialEq>(
+        &mut self,
+        field: &'static str,
+        base: &mut Option<T>,
+        other: &Option<T>,
+        extend: Option<fn(&mut T, &T)>,
+    ) -> bool {
+        let Some(other) = other else {
+            return false;
+        };
+        let Some(current) = base.as_mut() else {
+            *base = Some(other.clone());
+            return true;
+        };
+
+        match (self.options.strategy_for(field), extend) {
+            (MergeStrategy::Extend, Some(extend)) => {
+                extend(current, other);
+                true
+            }
+            _ if current == other => true,
+            (MergeStrategy::KeepBase, _) => {
+                self.outcomes.push((field, FieldOutcome::KeptBase));
+                false
+            }
+            (MergeStrategy::ErrorOnConflict, _) => {
+                self.outcomes.push((field, FieldOutcome::Conflict));
+                false
+            }
+            (MergeStrategy::Extend | MergeStrategy::Override, _) => {
+                *current = other.clone();
+                self.outcomes.push((field, FieldOutcome::Overridden));
+                true
+            }
+        }
+    }
+}
+
+impl CargoRunner {
+    /// Merges `other` on top of this config like [`CargoRunner::merge`], combining
+    /// fields set on both sides with the strategies of `options`.
+    ///
+    /// Nothing is merged if a field using [`MergeStrategy::ErrorOnConflict`]
+    /// differs, or if any resulting config has a `sub_command` outside of its
+    /// `allowed_subcommands`.
+    pub fn merge_with(
+        &mut self,
+        other: CargoRunner,
+        options: &MergeOptions,
+    ) -> Result<MergeReport> {
+        let mut merged = self.clone();
+        let mut report = MergeReport::default();
+        let mut conflicts = Vec::new();
+
+        for (context, (other_default, other_configs)) in other.0 {
+            let (base_default, base_configs) = merged
+                .0
+                .entry(context.clone())
+                .or_insert_with(|| (None, None));
+            let base = base_configs.get_or_insert_with(Vec::new);
+
+            for other_config in other_configs.into_iter().flatten() {
+                let Some(existing) = base.iter_mut().find(|c| c.name == other_config.name) else {
+                    other_config.check_sub_command()?;
+                    report.added.push((context.clone(), other_config.name.clone()));
+                    base.push(other_config);
+                    continue;
+                };
+
+                let mut config = existing.clone();
+                let mut fields = FieldMerger::new(options);
+                config.merge_fields(&other_config, &mut fields);
+                config.check_sub_command()?;
+                for (field, outcome) in fields.outcomes {
+                    let path = FieldPath {
+                        context: context.clone(),
+                        config: Some(config.name.clone()),
+                        field: field.to_string(),
+                    };
+                    report.record(path, outcome, &mut conflicts);
+                }
+                *existing = config;
+            }
+
+            if let Some(default) = other_default {
+                if !base.iter().any(|c| c.name == default) {
+                    report.rejected_defaults.push((context, default));
+                    continue;
+                }
+                let mut fields = FieldMerger::new(options);
+                fields.field("default", base_default, &Some(default), None);
+                for (field, outcome) in fields.outcomes {
+                    let path = FieldPath {
+                        context: context.clone(),
+                        config: None,
+                        field: field.to_string(),
+                    };
+                    report.record(path, outcome, &mut conflicts);
+                }
+            }
+        }
+
+        if !conflicts.is_empty() {
+            return Err(Error::MergeConflict(conflicts));
+        }
+        *self = merged;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Context;
+
+    const BASE: &str = r#"
+        [run]
+        default = "leptos"
+
+        [[run.config]]
+        name = "leptos"
+        command_type = "subcommand"
+        command = "leptos"
+        sub_command = "watch"
+        features = ["ssr"]
+
+        [run.config.env]
+        RUST_LOG = "info"
+
+        [[run.config]]
+        name = "dx"
+        command = "dx"
+        sub_command = "serve"
+
+        [test]
+        default = "nextest"
+    "#;
+
+    const OVERRIDES: &str = r#"
+        [run]
+        default = "dx"
+
+        [[run.config]]
+        name = "leptos"
+        sub_command = "serve"
+        features = ["hydrate"]
+
+        [run.config.env]
+        LEPTOS_ENV = "dev"
+
+        [test]
+        default = "missing"
+
+        [[test.config]]
+        name = "nextest"
+        command = "cargo"
+        sub_command = "nextest"
+    "#;
+
+    #[test]
+    fn test_merge_with_default_options() {
+        let mut runner: CargoRunner = BASE.parse().unwrap();
+
+        let report = runner
+            .merge_with(OVERRIDES.parse().unwrap(), &MergeOptions::default())
+            .unwrap();
+
+        let leptos = runner.find(Context::Run, "leptos").unwrap();
+        assert_eq!(leptos.sub_command.as_deref(), Some("serve"));
+        assert_eq!(leptos.features, Some(vec!["ssr".into(), "hydrate".into()]));
+        assert_eq!(leptos.env.as_ref().unwrap().len(), 2);
+        assert_eq!(runner.get_default(Context::Run), Some("dx"));
+
+        // The base `test` context had no configs, they are added all the same
+        assert!(runner.find(Context::Test, "nextest").is_some());
+        assert_eq!(runner.get_default(Context::Test), Some("nextest"));
+        assert_eq!(
+            report.to_string(),
+            "added config 'nextest' to 'test'\n\
+             overrode `run.leptos.sub_command`\n\
+             overrode `run.default`\n\
+             ignored default 'missing' of 'test', no such config"
+        );
+    }
+
+    #[test]
+    fn test_merge_with_strategies() {
+        let mut runner: CargoRunner = BASE.parse().unwrap();
+        let options = MergeOptions::new(MergeStrategy::KeepBase)
+            .field("features", MergeStrategy::Override)
+            .field("env", MergeStrategy::Extend);
+
+        let report = runner.merge_with(OVERRIDES.parse().unwrap(), &options).unwrap();
+
+        let leptos = runner.find(Context::Run, "leptos").unwrap();
+        assert_eq!(leptos.sub_command.as_deref(), Some("watch"));
+        assert_eq!(leptos.features, Some(vec!["hydrate".into()]));
+        assert_eq!(leptos.env.as_ref().unwrap().len(), 2);
+        assert_eq!(runner.get_default(Context::Run), Some("leptos"));
+        let conflicts: Vec<_> = report.conflicts.iter().map(FieldPath::to_string).collect();
+        assert_eq!(conflicts, ["run.leptos.sub_command", "run.default"]);
+
+        // Conflicts fail the whole merge
+        let before = runner.clone();
+        let options = MergeOptions::default().field("sub_command", MergeStrategy::ErrorOnConflict);
+        let err = runner.merge_with(OVERRIDES.parse().unwrap(), &options).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "conflicting values for `run.leptos.sub_command`, keep one or change the merge strategy"
+        );
+        assert_eq!(runner, before);
+    }
+}
@@ -0,0 +1,365 @@
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use toml_edit::{Decor, DocumentMut, Item, Table, TableLike, Value};
+
+use crate::{writer, CargoRunner, Error, Result};
+
+/// Version of the config layout this crate reads and writes, stored as the top-level `version` key.
+///
+/// Files without a `version` are treated as version 0, the layout used before
+/// versioning existed, and are upgraded when they use any of its older names.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Upgrades a document by one version, describing each change it makes.
+type Migration = fn(&mut DocumentMut, &mut Vec<String>);
+
+/// Every migration, indexed by the version it upgrades from.
+const MIGRATIONS: &[Migration] = &[v0_to_v1];
+
+/// What [`migrate`] changed in a config.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// The version the config was at, 0 when it had none.
+    pub from: u32,
+    pub to: u32,
+    /// One line per change, e.g. "renamed `run.commands` to `run.config`".
+    pub changes: Vec<String>,
+    /// Where the file was backed up to before it was rewritten.
+    pub backup: Option<PathBuf>,
+}
+
+impl MigrationReport {
+    /// Whether the config was already in the current layout.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl Display for MigrationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "config is up to date (version {})", self.to);
+        }
+
+        write!(f, "migrated config from version {} to {}", self.from, self.to)?;
+        for change in &self.changes {
+            write!(f, "\n  {}", change)?;
+        }
+        if let Some(backup) = &self.backup {
+            write!(f, "\nbackup created at: {}", backup.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Upgrades `document` to [`CONFIG_VERSION`] in memory.
+///
+/// A document that already uses the current layout is left untouched, even
+/// when it has no `version`. Versions newer than [`CONFIG_VERSION`] are left
+/// for the deserializer to reject.
+pub(crate) fn migrate(document: &mut DocumentMut) -> MigrationReport {
+    let from = match document.get("version").map(|item| item.as_integer()) {
+        None => 0,
+        Some(Some(version)) if (0..CONFIG_VERSION as i64).contains(&version) => version as u32,
+        Some(_) => {
+            return MigrationReport {
+                from: CONFIG_VERSION,
+                to: CONFIG_VERSION,
+                ..Default::default()
+            }
+        }
+    };
+
+    let mut changes = Vec::new();
+    for migration in &MIGRATIONS[from as usize..] {
+        migration(document, &mut changes);
+    }
+
+    if !changes.is_empty() || document.contains_key("version") {
+        changes.push(format!("set `version` to {}", CONFIG_VERSION));
+        set_version(document);
+    }
+
+    MigrationReport {
+        from,
+        to: CONFIG_VERSION,
+        changes,
+        backup: None,
+    }
+}
+
+/// `source` upgraded to the current layout, or unchanged when it needs no migration
+/// or is not valid TOML.
+pub(crate) fn upgrade(source: &str) -> Cow<'_, str> {
+    let Ok(mut document) = source.parse::<DocumentMut>() else {
+        return Cow::Borrowed(source);
+    };
+
+    if migrate(&mut document).is_empty() {
+        Cow::Borrowed(source)
+    } else {
+        Cow::Owned(document.to_string())
+    }
+}
+
+/// Sets the document's `version` to [`CONFIG_VERSION`].
+pub(crate) fn set_version(document: &mut DocumentMut) {
+    match document.get_mut("version") {
+        Some(item) => *item = toml_edit::value(CONFIG_VERSION as i64),
+        // Root values are always written above the first `[context]` header
+        None => {
+            let first = header_positions(document.as_table()).into_iter().min();
+            if let Some(decor) = first.and_then(|p| header_decor(document.as_table_mut(), p)) {
+                let prefix = decor.prefix().and_then(|p| p.as_str()).unwrap_or_default();
+                decor.set_prefix(format!("\n{}", prefix));
+            }
+            document.insert("version", toml_edit::value(CONFIG_VERSION as i64));
+        }
+    }
+}
+
+/// Positions of every header in `table`, such as `[run]` or `[[run.config]]`.
+fn header_positions(table: &Table) -> Vec<usize> {
+    let mut positions = Vec::new();
+    for (_, item) in table.iter() {
+        match item {
+            Item::Table(table) => {
+                positions.extend(table.position().filter(|_| !table.is_implicit()));
+                positions.extend(header_positions(table));
+            }
+            Item::ArrayOfTables(tables) => {
+                positions.extend(tables.iter().filter_map(Table::position));
+            }
+            _ => {}
+        }
+    }
+    positions
+}
+
+/// The decor of the header at `position`.
+fn header_decor(table: &mut Table, position: usize) -> Option<&mut Decor> {
+    for (_, item) in table.iter_mut() {
+        match item {
+            Item::Table(table) => {
+                if !table.is_implicit() && table.position() == Some(position) {
+                    return Some(table.decor_mut());
+                }
+                if let Some(decor) = header_decor(table, position) {
+                    return Some(decor);
+                }
+            }
+            Item::ArrayOfTables(tables) => {
+                if let Some(table) = tables.iter_mut().find(|t| t.position() == Some(position)) {
+                    return Some(table.decor_mut());
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Keys renamed since version 0, as `(old, new)`.
+pub(crate) const RENAMED_KEYS: &[(&str, &str)] = &[
+    ("commands", "config"),
+    ("allowed_subconfig", "allowed_subcommands"),
+];
+
+/// Pre-versioning layout: `[[$context.commands]]` and `allowed_subconfig`.
+fn v0_to_v1(document: &mut DocumentMut, changes: &mut Vec<String>) {
+    for (context, item) in document.iter_mut() {
+        let Some(table) = item.as_table_like_mut() else {
+            continue;
+        };
+
+        if rename_key(table, "commands", "config") {
+            changes.push(format!("renamed `{0}.commands` to `{0}.config`", context));
+        }
+
+        for config in configs_mut(table.get_mut("config")) {
+            let name = config
+                .get("name")
+                .and_then(Item::as_str)
+                .unwrap_or_default()
+                .to_string();
+
+            if rename_key(config, "allowed_subconfig", "allowed_subcommands") {
+                changes.push(format!(
+                    "renamed `allowed_subconfig` to `allowed_subcommands` in `{}.{}`",
+                    context, name
+                ));
+            }
+        }
+    }
+}
+
+/// Renames `from` to `to` in place, unless `to` already exists.
+fn rename_key(table: &mut dyn TableLike, from: &str, to: &str) -> bool {
+    if !table.contains_key(from) || table.contains_key(to) {
+        return false;
+    }
+
+    let keys: Vec<String> = table.iter().map(|(key, _)| key.to_string()).collect();
+    for key in keys {
+        if let Some(item) = table.remove(&key) {
+            let key = if key == from { to } else { key.as_str() };
+            table.insert(key, item);
+        }
+    }
+    true
+}
+
+fn configs_mut(item: Option<&mut Item>) -> Vec<&mut dyn TableLike> {
+    match item {
+        Some(Item::ArrayOfTables(tables)) => tables
+            .iter_mut()
+            .map(|t| t as &mut dyn TableLike)
+            .collect(),
+        Some(Item::Value(Value::Array(values))) => values
+            .iter_mut()
+            .filter_map(Value::as_inline_table_mut)
+            .map(|t| t as &mut dyn TableLike)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+impl CargoRunner {
+    /// Upgrades the config file at `path` to [`CONFIG_VERSION`], backing it up first.
+    ///
+    /// The file is only rewritten when something changed.
+    pub fn migrate_file(path: &Path) -> Result<MigrationReport> {
+        let (source, version) = writer::read(path)?;
+        let mut document: DocumentMut = source.parse().map_err(|e: toml_edit::TomlError| {
+            Error::Parse {
+                path: Some(path.to_path_buf()),
+                span: e.span(),
+                message: e.message().trim_end().to_string(),
+            }
+        })?;
+
+        let mut report = migrate(&mut document);
+        if report.is_empty() {
+            return Ok(report);
+        }
+
+        // Refuse to rewrite a file that would still not load
+        let migrated = document.to_string();
+        migrated
+            .parse::<CargoRunner>()
+            .map_err(|e| e.with_path(path))?;
+
+        report.backup = Self::create_backup(path)?;
+        writer::write(path, migrated.as_bytes(), Some(version))?;
+        Ok(report)
+    }
+
+    /// Loads the config at `path`, first upgrading the file with [`CargoRunner::migrate_file`].
+    pub fn load_and_migrate(path: &Path) -> Result<(CargoRunner, MigrationReport)> {
+        let report = Self::migrate_file(path)?;
+        Ok((Self::load(path.to_path_buf())?, report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::{CommandType, Context};
+
+    const LEGACY: &str = r#"# leptos preset
+[run]
+default = "leptos"
+
+[[run.commands]]
+name = "leptos"
+command_type = "sub_command" # runs `cargo leptos`
+command = "leptos"
+sub_command = "watch"
+allowed_subconfig = ["watch", "serve"]
+
+[run.commands.env]
+RUST_LOG = "debug"
+"#;
+
+    #[test]
+    fn test_migrate_legacy_layout() {
+        let mut document: DocumentMut = LEGACY.parse().unwrap();
+
+        let report = migrate(&mut document);
+
+        assert_eq!(report.from, 0);
+        assert_eq!(
+            report.changes,
+            vec![
+                "renamed `run.commands` to `run.config`",
+                "renamed `allowed_subconfig` to `allowed_subcommands` in `run.leptos`",
+                "set `version` to 1",
+            ]
+        );
+        assert_eq!(
+            document.to_string(),
+            r#"version = 1
+
+# leptos preset
+[run]
+default = "leptos"
+
+[[run.config]]
+name = "leptos"
+command_type = "sub_command" # runs `cargo leptos`
+command = "leptos"
+sub_command = "watch"
+allowed_subcommands = ["watch", "serve"]
+
+[run.config.env]
+RUST_LOG = "debug"
+"#
+        );
+
+        // Migrating again is a no-op
+        assert!(migrate(&mut document).is_empty());
+    }
+
+    #[test]
+    fn test_load_migrates_in_memory() {
+        let runner: CargoRunner = LEGACY.parse().unwrap();
+        let config = runner.find(Context::Run, "leptos").unwrap();
+
+        assert_eq!(config.command_type, Some(CommandType::SubCommand));
+        assert_eq!(config.allowed_subcommands.as_ref().unwrap().len(), 2);
+        assert_eq!(config.env.as_ref().unwrap()["RUST_LOG"], "debug");
+
+        // Current layouts without a version are left alone
+        let current = String::try_from(CargoRunner::default()).unwrap();
+        let current = current.trim_start_matches("version = 1\n\n");
+        assert!(matches!(upgrade(current), Cow::Borrowed(_)));
+
+        let err = "version = 2\n".parse::<CargoRunner>().unwrap_err();
+        assert!(err.to_string().contains("newer than"), "{}", err);
+    }
+
+    #[test]
+    fn test_migrate_file_backs_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, LEGACY).unwrap();
+
+        let (runner, report) = CargoRunner::load_and_migrate(&path).unwrap();
+
+        assert!(runner.find(Context::Run, "leptos").is_some());
+        let backup = report.backup.unwrap();
+        assert_eq!(fs::read_to_string(backup).unwrap(), LEGACY);
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .contains("[[run.config]]"));
+
+        let report = CargoRunner::migrate_file(&path).unwrap();
+        assert!(report.is_empty());
+        assert_eq!(report.backup, None);
+    }
+}
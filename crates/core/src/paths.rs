@@ -0,0 +1,114 @@
+use std::{
+    env,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use crate::{Error, Result};
+
+/// Environment variable that overrides where cargo-runner keeps its files.
+pub const HOME_ENV: &str = "CARGO_RUNNER_HOME";
+
+/// Where cargo-runner keeps the default config, its backups and the downloads cache.
+///
+/// [`ConfigPaths::from_env`] is what [`CargoRunner::init`](crate::CargoRunner::init)
+/// and friends use; pass a [`ConfigPaths::new`] to their `_with` variants to point
+/// them somewhere else, such as a temporary directory in tests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigPaths {
+    home: PathBuf,
+}
+
+impl ConfigPaths {
+    pub fn new(home: impl Into<PathBuf>) -> Self {
+        ConfigPaths { home: home.into() }
+    }
+
+    /// The first of:
+    ///
+    /// - `$CARGO_RUNNER_HOME`
+    /// - `$XDG_CONFIG_HOME/cargo-runner`, unless only `~/.cargo-runner` exists
+    /// - `~/.cargo-runner`
+    pub fn from_env() -> Result<Self> {
+        resolve_home(env::var_os(HOME_ENV), env::var_os("XDG_CONFIG_HOME"), dirs::home_dir())
+            .map(ConfigPaths::new)
+    }
+
+    /// The directory every other path is in.
+    pub fn home(&self) -> &Path {
+        &self.home
+    }
+
+    /// The default config, `config.toml` in [`ConfigPaths::home`].
+    pub fn config_file(&self) -> PathBuf {
+        self.home.join("config.toml")
+    }
+
+    /// Where downloaded presets are cached, `cache` in [`ConfigPaths::home`].
+    pub fn cache_dir(&self) -> PathBuf {
+        self.home.join("cache")
+    }
+}
+
+fn resolve_home(
+    cargo_runner_home: Option<OsString>,
+    xdg_config_home: Option<OsString>,
+    home: Option<PathBuf>,
+) -> Result<PathBuf> {
+    if let Some(dir) = cargo_runner_home.filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+
+    let legacy = home.map(|home| home.join(".cargo-runner"));
+    // The XDG spec says relative paths are to be ignored
+    let xdg = xdg_config_home
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .map(|dir| dir.join("cargo-runner"));
+
+    match (xdg, legacy) {
+        // Keep using the config of users who set it up before XDG was supported
+        (Some(xdg), Some(legacy)) if !xdg.exists() && legacy.exists() => Ok(legacy),
+        (Some(xdg), _) => Ok(xdg),
+        (None, Some(legacy)) => Ok(legacy),
+        (None, None) => Err(Error::MissingHomeDir),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_home() {
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().join("home");
+        let xdg = dir.path().join("config");
+
+        let resolve = |cargo_runner_home: Option<&str>, xdg: Option<&Path>, home: Option<&Path>| {
+            resolve_home(
+                cargo_runner_home.map(OsString::from),
+                xdg.map(OsString::from),
+                home.map(Path::to_path_buf),
+            )
+        };
+
+        assert_eq!(
+            resolve(Some("/ci/runner"), Some(&xdg), Some(&home)).unwrap(),
+            PathBuf::from("/ci/runner")
+        );
+        assert_eq!(resolve(Some(""), None, Some(&home)).unwrap(), home.join(".cargo-runner"));
+        assert_eq!(resolve(None, Some(&xdg), Some(&home)).unwrap(), xdg.join("cargo-runner"));
+        assert_eq!(
+            resolve(None, Some(Path::new("relative")), Some(&home)).unwrap(),
+            home.join(".cargo-runner")
+        );
+        assert!(matches!(resolve(None, None, None), Err(Error::MissingHomeDir)));
+
+        // An existing legacy directory wins until the XDG one is created
+        std::fs::create_dir_all(home.join(".cargo-runner")).unwrap();
+        assert_eq!(resolve(None, Some(&xdg), Some(&home)).unwrap(), home.join(".cargo-runner"));
+        std::fs::create_dir_all(xdg.join("cargo-runner")).unwrap();
+        assert_eq!(resolve(None, Some(&xdg), Some(&home)).unwrap(), xdg.join("cargo-runner"));
+    }
+}
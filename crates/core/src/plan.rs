@@ -0,0 +1,515 @@
+use std::{
+    ffi::OsString,
+    fmt::Display,
+    path::{Path, PathBuf},
+    process,
+};
+
+use indexmap::IndexMap;
+
+use crate::{
+    CargoRunner, CommandType, Config, Context, Error, FailurePolicy, Hook, Result, Variables,
+};
+
+/// A command ready to run, with every placeholder expanded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandPlan {
+    pub program: String,
+    pub args: Vec<String>,
+    /// Directory to run in, the current directory when `None`.
+    pub cwd: Option<PathBuf>,
+    /// Whether to start from an empty environment instead of inheriting one.
+    pub clear_env: bool,
+    /// Inherited variables to unset.
+    pub env_remove: Vec<String>,
+    /// Directories put in front of `PATH`.
+    pub path_prepend: Vec<PathBuf>,
+    /// Set on top of the inherited environment.
+    pub env: IndexMap<String, String>,
+    /// Hooks run before the command, in order.
+    pub pre: Vec<HookPlan>,
+    /// Hooks run after the command succeeds, in order.
+    pub post: Vec<HookPlan>,
+}
+
+/// A `pre` or `post` hook ready to run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HookPlan {
+    pub name: Option<String>,
+    pub on_failure: FailurePolicy,
+    pub plan: CommandPlan,
+}
+
+impl CommandPlan {
+    /// The command a config describes, taken as is:
+    ///
+    /// - `cargo`: `<command or cargo> [+toolchain] <sub_command> [cargo options] [args] [-- extra_args]`
+    /// - `subcommand`: `cargo [+toolchain] <command> <sub_command> [cargo options] [args] [-- extra_args]`
+    /// - `shell`: `<command> <sub_command> [args] [-- extra_args]`
+    ///
+    /// Cargo options are `--profile`, `--target`, `--features`, `--all-features`
+    /// and `--no-default-features`; `shell` commands ignore them and `toolchain`.
+    ///
+    /// Hooks get the config's environment with their own `env` on top, and its
+    /// `cwd` unless they set their own.
+    pub fn from_config(config: &Config) -> Self {
+        let command = config.command.clone();
+        let toolchain = config.toolchain.iter().map(|t| format!("+{}", t));
+        let command_type = config.command_type.clone().unwrap_or_default();
+        let (program, mut args): (_, Vec<_>) = match command_type {
+            CommandType::Cargo => (
+                command.unwrap_or_else(|| "cargo".to_string()),
+                toolchain.collect(),
+            ),
+            CommandType::SubCommand => (
+                "cargo".to_string(),
+                toolchain.chain(command).collect(),
+            ),
+            CommandType::Shell => (command.unwrap_or_default(), Vec::new()),
+        };
+        args.extend(config.sub_command.clone());
+
+        if command_type != CommandType::Shell {
+            args.extend(cargo_options(config));
+        }
+        args.extend(config.args.iter().flatten().cloned());
+        if let Some(extra_args) = config.extra_args.as_ref().filter(|a| !a.is_empty()) {
+            args.push("--".to_string());
+            args.extend(extra_args.iter().cloned());
+        }
+
+        let mut plan = CommandPlan {
+            program,
+            args,
+            cwd: config.cwd.as_ref().map(PathBuf::from),
+            clear_env: config.clear_env == Some(true),
+            env_remove: config.env_remove.clone().unwrap_or_default(),
+            path_prepend: config.path_prepend.iter().flatten().map(PathBuf::from).collect(),
+            env: config.env.clone().unwrap_or_default(),
+            pre: Vec::new(),
+            post: Vec::new(),
+        };
+        plan.pre = config.pre.iter().flatten().map(|hook| plan.hook(hook)).collect();
+        plan.post = config.post.iter().flatten().map(|hook| plan.hook(hook)).collect();
+        plan
+    }
+
+    fn hook(&self, hook: &Hook) -> HookPlan {
+        let mut env = self.env.clone();
+        env.extend(hook.env.clone().unwrap_or_default());
+
+        HookPlan {
+            name: hook.name.clone(),
+            on_failure: hook.on_failure.unwrap_or_default(),
+            plan: CommandPlan {
+                program: hook.command.clone(),
+                args: hook.args.clone().unwrap_or_default(),
+                cwd: hook.cwd.as_ref().map(PathBuf::from).or_else(|| self.cwd.clone()),
+                clear_env: self.clear_env,
+                env_remove: self.env_remove.clone(),
+                path_prepend: self.path_prepend.clone(),
+                env,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// A [`process::Command`] that runs the command, without its hooks.
+    pub fn command(&self) -> process::Command {
+        let mut command = process::Command::new(&self.program);
+        command.args(&self.args);
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+        if self.clear_env {
+            command.env_clear();
+        }
+        for key in &self.env_remove {
+            command.env_remove(key);
+        }
+        command.envs(&self.env);
+
+        if !self.path_prepend.is_empty() {
+            let base = match self.env.get("PATH") {
+                Some(path) => Some(OsString::from(path)),
+                None if self.inherits_path() => std::env::var_os("PATH"),
+                None => None,
+            };
+            let paths = self
+                .path_prepend
+                .iter()
+                .cloned()
+                .chain(base.iter().flat_map(std::env::split_paths));
+            if let Ok(path) = std::env::join_paths(paths) {
+                command.env("PATH", path);
+            }
+        }
+        command
+    }
+
+    fn inherits_path(&self) -> bool {
+        !self.clear_env && !self.env_remove.iter().any(|key| key == "PATH")
+    }
+}
+
+fn cargo_options(config: &Config) -> Vec<String> {
+    let mut options = Vec::new();
+    if let Some(profile) = &config.profile {
+        options.extend(["--profile".to_string(), profile.clone()]);
+    }
+    if let Some(target) = &config.target {
+        options.extend(["--target".to_string(), target.clone()]);
+    }
+    if let Some(features) = config.features.as_ref().filter(|f| !f.is_empty()) {
+        options.extend(["--features".to_string(), features.join(",")]);
+    }
+    if config.all_features == Some(true) {
+        options.push("--all-features".to_string());
+    }
+    if config.no_default_features == Some(true) {
+        options.push("--no-default-features".to_string());
+    }
+    options
+}
+
+/// The command as it would be typed in a POSIX shell, e.g. `RUST_LOG=debug cargo run`
+/// or `cd /work/app && env -u CARGO_TARGET_DIR cargo run`.
+///
+/// With hooks, one line per step as a script meant for `sh -e`: a step that sets
+/// `cwd` runs in a subshell and a hook that may fail ends with `|| true`.
+impl Display for CommandPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.pre.is_empty() && self.post.is_empty() {
+            return self.write_line(f, false);
+        }
+
+        for hook in &self.pre {
+            writeln!(f, "{}", hook)?;
+        }
+        self.write_line(f, true)?;
+        for hook in &self.post {
+            write!(f, "\n{}", hook)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for HookPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.plan.write_line(f, true)?;
+        if self.on_failure == FailurePolicy::Continue {
+            write!(f, " || true")?;
+        }
+        Ok(())
+    }
+}
+
+impl CommandPlan {
+    fn write_line(&self, f: &mut std::fmt::Formatter<'_>, subshell: bool) -> std::fmt::Result {
+        let subshell = subshell && self.cwd.is_some();
+        if subshell {
+            write!(f, "(")?;
+        }
+
+        let mut prefix = Vec::new();
+        if let Some(cwd) = &self.cwd {
+            prefix.extend(["cd".to_string(), quote_path(cwd), "&&".to_string()]);
+        }
+        if self.clear_env {
+            prefix.extend(["env".to_string(), "-i".to_string()]);
+        } else if !self.env_remove.is_empty() {
+            prefix.push("env".to_string());
+            for key in &self.env_remove {
+                prefix.extend(["-u".to_string(), quote(key)]);
+            }
+        }
+        if !self.path_prepend.is_empty() {
+            let mut paths: Vec<_> = self.path_prepend.iter().map(|p| quote_path(p)).collect();
+            match self.env.get("PATH") {
+                Some(path) => paths.push(quote(path)),
+                None if self.inherits_path() => paths.push("\"$PATH\"".to_string()),
+                None => {}
+            }
+            prefix.push(format!("PATH={}", paths.join(":")));
+        }
+
+        let mut words = prefix
+            .into_iter()
+            .chain(
+                self.env
+                    .iter()
+                    .filter(|(key, _)| self.path_prepend.is_empty() || *key != "PATH")
+                    .map(|(key, value)| format!("{}={}", key, quote(value))),
+            )
+            .chain(std::iter::once(quote(&self.program)))
+            .chain(self.args.iter().map(|arg| quote(arg)));
+
+        if let Some(first) = words.next() {
+            write!(f, "{}", first)?;
+        }
+        for word in words {
+            write!(f, " {}", word)?;
+        }
+        if subshell {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+fn quote_path(path: &Path) -> String {
+    quote(&path.to_string_lossy())
+}
+
+fn quote(word: &str) -> String {
+    let plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+
+    if plain {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+impl CargoRunner {
+    /// The command for the config `name` of `context`, or the context's default when
+    /// `name` is `None`, with placeholders expanded by [`CargoRunner::expand`].
+    ///
+    /// A relative `cwd`, the config's or a hook's, is resolved against the package
+    /// root, and relative `path_prepend` entries against the config's resolved `cwd`.
+    ///
+    /// Fails when the expanded `sub_command` is not one of the config's `allowed_subcommands`.
+    pub fn plan(
+        &self,
+        context: Context,
+        name: Option<&str>,
+        variables: &Variables,
+    ) -> Result<CommandPlan> {
+        let name = name
+            .or_else(|| self.get_default(context.clone()))
+            .ok_or_else(|| Error::MissingDefault(context.to_string()))?;
+
+        let config = self.expand(context, name, variables)?;
+        config.check_sub_command()?;
+
+        let mut plan = CommandPlan::from_config(&config);
+        let root = variables.package_root.as_deref();
+        plan.cwd = plan.cwd.map(|cwd| resolve_path(root, cwd));
+        let base = plan.cwd.as_deref().or(root);
+        plan.path_prepend = plan
+            .path_prepend
+            .into_iter()
+            .map(|path| resolve_path(base, path))
+            .collect();
+        for hook in plan.pre.iter_mut().chain(plan.post.iter_mut()) {
+            hook.plan.cwd = hook.plan.cwd.take().map(|cwd| resolve_path(root, cwd));
+            hook.plan.path_prepend = plan.path_prepend.clone();
+        }
+        Ok(plan)
+    }
+}
+
+fn resolve_path(base: Option<&Path>, path: PathBuf) -> PathBuf {
+    match base {
+        Some(base) if path.is_relative() => base.join(path).components().collect(),
+        _ => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_command_types() {
+        let runner: CargoRunner = r#"
+            [run]
+            default = "leptos"
+
+            [[run.config]]
+            name = "leptos"
+            command_type = "subcommand"
+            command = "leptos"
+            sub_command = "watch"
+            env = { LEPTOS_SITE_ROOT = "${packageRoot}/my site" }
+
+            [[run.config]]
+            name = "dx"
+            command_type = "shell"
+            command = "dx"
+            sub_command = "serve"
+        "#
+        .parse()
+        .unwrap();
+        let variables = Variables {
+            package_root: Some("/work/app".into()),
+            ..Default::default()
+        };
+
+        let plan = runner.plan(Context::Run, None, &variables).unwrap();
+        assert_eq!(plan.program, "cargo");
+        assert_eq!(plan.args, ["leptos", "watch"]);
+        assert_eq!(
+            plan.to_string(),
+            "LEPTOS_SITE_ROOT='/work/app/my site' cargo leptos watch"
+        );
+
+        let plan = runner.plan(Context::Run, Some("dx"), &variables).unwrap();
+        assert_eq!(plan.to_string(), "dx serve");
+
+        let plan = CargoRunner::default()
+            .plan(Context::Test, None, &variables)
+            .unwrap();
+        assert_eq!(plan.to_string(), "cargo test");
+    }
+
+    #[test]
+    fn test_plan_cargo_options() {
+        let mut runner: CargoRunner = r#"
+            [[test.config]]
+            name = "nightly"
+            toolchain = "nightly"
+            sub_command = "test"
+            profile = "release"
+            target = "wasm32-unknown-unknown"
+            features = ["ssr"]
+            no_default_features = true
+            args = ["-p", "${package}"]
+            extra_args = ["--nocapture"]
+        "#
+        .parse()
+        .unwrap();
+        let overrides: CargoRunner = r#"
+            [[test.config]]
+            name = "nightly"
+            features = ["ssr", "hydrate"]
+            extra_args = ["--test-threads=1"]
+        "#
+        .parse()
+        .unwrap();
+        runner.merge(overrides).unwrap();
+        let variables = Variables {
+            package: Some("app".to_string()),
+            ..Default::default()
+        };
+
+        let plan = runner.plan(Context::Test, Some("nightly"), &variables).unwrap();
+
+        assert_eq!(
+            plan.to_string(),
+            "cargo +nightly test --profile release --target wasm32-unknown-unknown \
+             --features ssr,hydrate --no-default-features -p app -- --nocapture --test-threads=1"
+        );
+    }
+
+    #[test]
+    fn test_plan_environment() {
+        let mut runner: CargoRunner = r#"
+            [[run.config]]
+            name = "tauri"
+            command_type = "subcommand"
+            command = "tauri"
+            sub_command = "dev"
+            cwd = "src-tauri"
+            env = { CARGO_TARGET_DIR = "/tmp/target", RUST_LOG = "info" }
+        "#
+        .parse()
+        .unwrap();
+        let overrides: CargoRunner = r#"
+            [[run.config]]
+            name = "tauri"
+            env_remove = ["CARGO_TARGET_DIR"]
+            path_prepend = ["node_modules/.bin", "/opt/bin"]
+        "#
+        .parse()
+        .unwrap();
+        runner.merge(overrides).unwrap();
+
+        let config = runner.find(Context::Run, "tauri").unwrap();
+        assert_eq!(config.env.as_ref().unwrap().keys().collect::<Vec<_>>(), ["RUST_LOG"]);
+
+        let variables = Variables {
+            package_root: Some("/work/app".into()),
+            ..Default::default()
+        };
+        let plan = runner.plan(Context::Run, Some("tauri"), &variables).unwrap();
+        assert_eq!(plan.cwd.as_deref(), Some(Path::new("/work/app/src-tauri")));
+        assert_eq!(
+            plan.to_string(),
+            "cd /work/app/src-tauri && env -u CARGO_TARGET_DIR \
+             PATH=/work/app/src-tauri/node_modules/.bin:/opt/bin:\"$PATH\" RUST_LOG=info cargo tauri dev"
+        );
+
+        let command = plan.command();
+        assert_eq!(command.get_current_dir(), Some(Path::new("/work/app/src-tauri")));
+        let envs: Vec<_> = command.get_envs().collect();
+        assert!(envs.contains(&("CARGO_TARGET_DIR".as_ref(), None)));
+        let path = envs.iter().find(|(key, _)| *key == "PATH").unwrap().1.unwrap();
+        assert!(path
+            .to_string_lossy()
+            .starts_with("/work/app/src-tauri/node_modules/.bin:/opt/bin"));
+
+        let hermetic = CommandPlan {
+            program: "cargo".to_string(),
+            clear_env: true,
+            path_prepend: vec!["/opt/bin".into()],
+            ..Default::default()
+        };
+        assert_eq!(hermetic.to_string(), "env -i PATH=/opt/bin cargo");
+        let command = hermetic.command();
+        let path = command.get_envs().find(|(key, _)| *key == "PATH").unwrap().1;
+        assert_eq!(path, Some("/opt/bin".as_ref()));
+    }
+
+    #[test]
+    fn test_plan_hooks() {
+        let runner: CargoRunner = r#"
+            [run]
+            default = "leptos"
+
+            [[run.config]]
+            name = "leptos"
+            command_type = "subcommand"
+            command = "leptos"
+            sub_command = "watch"
+            env = { RUST_LOG = "info" }
+
+            [[run.config.pre]]
+            command = "npm"
+            args = ["run", "build:css"]
+            cwd = "style"
+
+            [[run.config.pre]]
+            command = "sqlx"
+            args = ["migrate", "run"]
+            on_failure = "continue"
+            env = { DATABASE_URL = "sqlite:${package}.db" }
+
+            [[run.config.post]]
+            command = "docker"
+            args = ["compose", "down"]
+        "#
+        .parse()
+        .unwrap();
+        let variables = Variables {
+            package_root: Some("/work/app".into()),
+            package: Some("app".to_string()),
+            ..Default::default()
+        };
+
+        let plan = runner.plan(Context::Run, None, &variables).unwrap();
+
+        assert_eq!(plan.pre[0].plan.cwd.as_deref(), Some(Path::new("/work/app/style")));
+        assert_eq!(plan.pre[1].on_failure, FailurePolicy::Continue);
+        assert_eq!(
+            plan.to_string(),
+            "(cd /work/app/style && RUST_LOG=info npm run build:css)
+RUST_LOG=info DATABASE_URL=sqlite:app.db sqlx migrate run || true
+RUST_LOG=info cargo leptos watch
+RUST_LOG=info docker compose down"
+        );
+    }
+}
@@ -0,0 +1,342 @@
+use std::{fmt::Display, path::PathBuf};
+
+use indexmap::IndexMap;
+
+use crate::{CargoRunner, Config, Context, MergeReport};
+
+/// Where a setting came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    BuiltIn,
+    File(PathBuf),
+    Url(String),
+    Cli,
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Origin::BuiltIn => write!(f, "built-in"),
+            Origin::File(path) => write!(f, "{}", path.display()),
+            Origin::Url(url) => write!(f, "{}", url),
+            Origin::Cli => write!(f, "command line"),
+        }
+    }
+}
+
+/// A value set by one source, rendered as TOML.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assignment {
+    pub origin: Origin,
+    pub value: String,
+}
+
+/// Every assignment of one setting, oldest first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace(pub Vec<Assignment>);
+
+impl Trace {
+    /// The assignment that is in effect.
+    pub fn effective(&self) -> Option<&Assignment> {
+        self.0.last()
+    }
+
+    /// The assignments that were shadowed by the effective one, oldest first.
+    pub fn shadowed(&self) -> &[Assignment] {
+        &self.0[..self.0.len().saturating_sub(1)]
+    }
+
+    fn push(&mut self, origin: &Origin, value: String) {
+        self.0.push(Assignment {
+            origin: origin.clone(),
+            value,
+        });
+    }
+}
+
+/// Records which source set each context default, config and config field.
+///
+/// Env variables are tracked per key as `env.$KEY` fields.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    defaults: IndexMap<String, Trace>,
+    configs: IndexMap<(String, String), IndexMap<String, Trace>>,
+}
+
+impl Provenance {
+    /// Records what `other` contributed once it has been merged into `merged`,
+    /// `report` being what [`CargoRunner::merge_with`] returned for it.
+    ///
+    /// Values are read back from `merged`, so fields that accumulate across
+    /// sources show their combined value. Defaults are only recorded when the
+    /// merge took them, not when it ignored them or kept the base.
+    pub fn record(
+        &mut self,
+        origin: &Origin,
+        other: &CargoRunner,
+        merged: &CargoRunner,
+        report: &MergeReport,
+    ) {
+        for (context, (default, configs)) in &other.0 {
+            let Some((_, merged_configs)) = merged.0.get(context) else {
+                continue;
+            };
+
+            if let Some(default) = default {
+                let rejected = report
+                    .rejected_defaults
+                    .iter()
+                    .any(|(c, d)| c == context && d == default);
+                let kept_base = report
+                    .conflicts
+                    .iter()
+                    .any(|path| path.context == *context && path.config.is_none());
+                if !rejected && !kept_base {
+                    self.defaults
+                        .entry(context.clone())
+                        .or_default()
+                        .push(origin, toml::Value::from(default.as_str()).to_string());
+                }
+            }
+
+            for config in configs.iter().flatten() {
+                let Some(merged_config) = merged_configs
+                    .iter()
+                    .flatten()
+                    .find(|c| c.name == config.name)
+                else {
+                    continue;
+                };
+
+                let fields = self
+                    .configs
+                    .entry((context.clone(), config.name.clone()))
+                    .or_default();
+                let merged_fields = fields_of(merged_config);
+
+                for key in fields_of(config).keys() {
+                    if let Some(value) = merged_fields.get(key) {
+                        fields.entry(key.clone()).or_default().push(origin, value.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Where the default of `context` came from.
+    pub fn default_of(&self, context: &str) -> Option<&Trace> {
+        self.defaults.get(context)
+    }
+
+    /// Where each field of the config `name` in `context` came from.
+    pub fn fields_of(&self, context: &str, name: &str) -> Option<&IndexMap<String, Trace>> {
+        self.configs.get(&(context.to_string(), name.to_string()))
+    }
+
+    /// Explains the config `name` of `context`, or the context's default when `name` is `None`.
+    pub fn explain(
+        &self,
+        runner: &CargoRunner,
+        context: Context,
+        name: Option<&str>,
+    ) -> Option<Explanation> {
+        let key = context.as_str();
+        let default = runner.get_default(context.clone());
+        let name = name.or(default)?;
+        runner.find(context.clone(), name)?;
+
+        Some(Explanation {
+            context: key.to_string(),
+            name: name.to_string(),
+            is_default: default == Some(name),
+            default: self.default_of(key).cloned(),
+            fields: self.fields_of(key, name).cloned().unwrap_or_default(),
+        })
+    }
+}
+
+/// Every field of `config` except its name, rendered as TOML, with env variables flattened.
+fn fields_of(config: &Config) -> IndexMap<String, String> {
+    let mut fields = IndexMap::new();
+    let Ok(document) = toml_edit::ser::to_document(config) else {
+        return fields;
+    };
+
+    for (key, item) in document.iter() {
+        match (key, item.as_table_like()) {
+            ("name", _) => {}
+            ("env", Some(env)) => {
+                for (env_key, env_value) in env.iter() {
+                    fields.insert(format!("env.{}", env_key), render(env_value));
+                }
+            }
+            _ => {
+                fields.insert(key.to_string(), render(item));
+            }
+        }
+    }
+
+    fields
+}
+
+fn render(item: &toml_edit::Item) -> String {
+    match item.as_value() {
+        Some(value) => value.clone().decorated("", "").to_string(),
+        None => item.to_string().trim().to_string(),
+    }
+}
+
+/// Where every effective setting of one config came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub context: String,
+    pub name: String,
+    /// Whether the config is the default of its context.
+    pub is_default: bool,
+    /// Where the context's default came from.
+    pub default: Option<Trace>,
+    /// Where each field came from, keyed by field name or `env.$KEY`.
+    pub fields: IndexMap<String, Trace>,
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(default) = &self.default {
+            writeln!(f, "[{}]", self.context)?;
+            write_trace(f, "default", default)?;
+            writeln!(f)?;
+        }
+
+        writeln!(f, "[{}.{}]", self.context, self.name)?;
+        for (field, trace) in &self.fields {
+            write_trace(f, field, trace)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_trace(f: &mut std::fmt::Formatter<'_>, key: &str, trace: &Trace) -> std::fmt::Result {
+    let Some(effective) = trace.effective() else {
+        return Ok(());
+    };
+
+    writeln!(f, "{} = {}", key, effective.value)?;
+    writeln!(f, "    set by {}", effective.origin)?;
+    for shadowed in trace.shadowed().iter().rev() {
+        writeln!(f, "    shadows {} from {}", shadowed.value, shadowed.origin)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MergeOptions;
+
+    #[test]
+    fn test_explain_tracks_sources() {
+        let mut runner = CargoRunner::default();
+        let mut provenance = Provenance::default();
+        provenance.record(&Origin::BuiltIn, &runner.clone(), &runner, &MergeReport::default());
+
+        let leptos: CargoRunner = r#"
+            [run]
+            default = "leptos"
+
+            [[run.config]]
+            name = "leptos"
+            command_type = "subcommand"
+            command = "leptos"
+            sub_command = "watch"
+        "#
+        .parse()
+        .unwrap();
+        let global = Origin::File(PathBuf::from("/home/me/.cargo-runner/config.toml"));
+        let report = runner.merge_with(leptos.clone(), &MergeOptions::default()).unwrap();
+        provenance.record(&global, &leptos, &runner, &report);
+
+        let overrides: CargoRunner = r#"
+            [run]
+            default = "missing"
+
+            [[run.config]]
+            name = "leptos"
+            sub_command = "serve"
+
+            [run.config.env]
+            RUST_LOG = "debug"
+        "#
+        .parse()
+        .unwrap();
+        let report = runner.merge_with(overrides.clone(), &MergeOptions::default()).unwrap();
+        provenance.record(&Origin::Cli, &overrides, &runner, &report);
+
+        let explanation = provenance.explain(&runner, Context::Run, None).unwrap();
+
+        assert_eq!(explanation.name, "leptos");
+        assert!(explanation.is_default);
+
+        let default = explanation.default.as_ref().unwrap();
+        assert_eq!(default.effective().unwrap().origin, global);
+        assert_eq!(default.shadowed()[0].origin, Origin::BuiltIn);
+
+        let sub_command = &explanation.fields["sub_command"];
+        assert_eq!(sub_command.effective().unwrap().value, "\"serve\"");
+        assert_eq!(sub_command.effective().unwrap().origin, Origin::Cli);
+        assert_eq!(sub_command.shadowed()[0].value, "\"watch\"");
+        assert_eq!(explanation.fields["command"].effective().unwrap().origin, global);
+        assert_eq!(
+            explanation.fields["env.RUST_LOG"].effective().unwrap().origin,
+            Origin::Cli
+        );
+
+        assert_eq!(
+            explanation.to_string(),
+            r#"[run]
+default = "leptos"
+    set by /home/me/.cargo-runner/config.toml
+    shadows "default" from built-in
+
+[run.leptos]
+command_type = "subcommand"
+    set by /home/me/.cargo-runner/config.toml
+command = "leptos"
+    set by /home/me/.cargo-runner/config.toml
+sub_command = "serve"
+    set by command line
+    shadows "watch" from /home/me/.cargo-runner/config.toml
+env.RUST_LOG = "debug"
+    set by command line
+"#
+        );
+    }
+
+    #[test]
+    fn test_default_is_credited_to_layers_that_set_it() {
+        let dx: CargoRunner = "[run]\ndefault = \"dx\"\n\n[[run.config]]\nname = \"dx\"\n"
+            .parse()
+            .unwrap();
+        let same_default: CargoRunner = "[run]\ndefault = \"dx\"\n".parse().unwrap();
+        let global = Origin::File(PathBuf::from("/home/me/.cargo-runner/config.toml"));
+        let workspace = Origin::File(PathBuf::from("/work/cargo-runner.toml"));
+
+        let mut runner = CargoRunner::default();
+        let mut provenance = Provenance::default();
+        for (origin, layer) in [(&global, &dx), (&workspace, &same_default)] {
+            let report = runner.merge_with(layer.clone(), &MergeOptions::default()).unwrap();
+            provenance.record(origin, layer, &runner, &report);
+        }
+        let default = provenance.default_of("run").unwrap();
+        let origins: Vec<_> = default.0.iter().map(|a| &a.origin).collect();
+        assert_eq!(origins, [&global, &workspace]);
+
+        // A default the merge ignores is not credited, even if it matches the current one
+        let base = IndexMap::from([("run".to_string(), (Some("dx".to_string()), None))]);
+        let mut runner = CargoRunner(base);
+        let mut provenance = Provenance::default();
+        let report = runner.merge_with(same_default.clone(), &MergeOptions::default()).unwrap();
+        assert_eq!(report.rejected_defaults, [("run".to_string(), "dx".to_string())]);
+        provenance.record(&workspace, &same_default, &runner, &report);
+        assert_eq!(provenance.default_of("run"), None);
+    }
+}
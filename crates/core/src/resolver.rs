@@ -0,0 +1,527 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use indexmap::IndexMap;
+
+use crate::{
+    CargoRunner, Config, Context, Error, Explanation, Lockfile, MergeOptions, MergeReport, Origin,
+    Provenance, Result,
+};
+
+/// Name of the per-project config file looked up next to `Cargo.toml`.
+pub const CONFIG_FILE_NAME: &str = "cargo-runner.toml";
+
+/// One source of configuration, in the order [`Resolver`] merges them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Layer {
+    /// [`CargoRunner::default`].
+    BuiltIn,
+    /// The default config, `~/.cargo-runner/config.toml` unless moved with [`ConfigPaths`](crate::ConfigPaths).
+    Global(PathBuf),
+    /// `cargo-runner.toml` at the root of the cargo workspace.
+    Workspace(PathBuf),
+    /// `cargo-runner.toml` next to the package's `Cargo.toml`.
+    Package(PathBuf),
+    /// The nearest `cargo-runner.toml` between the source file and the package root.
+    Local(PathBuf),
+}
+
+impl Layer {
+    /// The file this layer was read from, `None` for the built-in configs.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Layer::BuiltIn => None,
+            Layer::Global(path)
+            | Layer::Workspace(path)
+            | Layer::Package(path)
+            | Layer::Local(path) => Some(path),
+        }
+    }
+
+    /// The [`Origin`] recorded for settings this layer provides.
+    pub fn origin(&self) -> Origin {
+        match self.path() {
+            Some(path) => Origin::File(path.to_path_buf()),
+            None => Origin::BuiltIn,
+        }
+    }
+}
+
+/// The effective config for a source file.
+#[derive(Debug, Clone)]
+pub struct Resolved {
+    pub runner: CargoRunner,
+    /// Every layer that contributed, lowest precedence first.
+    pub layers: Vec<Layer>,
+    /// Which layer set each default, config and field.
+    pub provenance: Provenance,
+}
+
+impl Resolved {
+    /// The files that contributed, lowest precedence first.
+    pub fn files(&self) -> Vec<&Path> {
+        self.layers.iter().filter_map(Layer::path).collect()
+    }
+
+    /// Merges another source on top, such as overrides given on the command line.
+    ///
+    /// Fails without changing anything when `other` sets a `sub_command` the
+    /// config's `allowed_subcommands` do not permit.
+    pub fn merge(&mut self, other: CargoRunner, origin: Origin) -> Result<()> {
+        let report = self.runner.merge_with(other.clone(), &MergeOptions::default())?;
+        self.provenance.record(&origin, &other, &self.runner, &report);
+        Ok(())
+    }
+
+    /// Merges a layer, recording the configs still as a downloaded preset left them
+    /// with that preset's source as their origin.
+    fn merge_layer(
+        &mut self,
+        mut other: CargoRunner,
+        origin: Origin,
+        downloaded: Vec<(String, String, Origin)>,
+    ) -> Result<()> {
+        let report = self.runner.merge_with(other.clone(), &MergeOptions::default())?;
+
+        for (context, name, preset_origin) in downloaded {
+            let Some((_, Some(configs))) = other.0.get_mut(&context) else {
+                continue;
+            };
+            let Some(position) = configs.iter().position(|config| config.name == name) else {
+                continue;
+            };
+            let config = configs.remove(position);
+            let preset = IndexMap::from([(context, (None, Some(vec![config])))]);
+            let preset = CargoRunner(preset);
+            self.provenance.record(&preset_origin, &preset, &self.runner, &MergeReport::default());
+        }
+        self.provenance.record(&origin, &other, &self.runner, &report);
+        Ok(())
+    }
+
+    /// Overrides the `sub_command` of the config `name` in `context`, or of the
+    /// context's default when `name` is `None`, as if given on the command line.
+    pub fn set_sub_command(
+        &mut self,
+        context: Context,
+        name: Option<&str>,
+        sub_command: &str,
+    ) -> Result<()> {
+        let name = name
+            .or_else(|| self.runner.get_default(context.clone()))
+            .ok_or_else(|| Error::MissingDefault(context.to_string()))?
+            .to_string();
+        if self.runner.find(context.clone(), &name).is_none() {
+            return Err(Error::UnknownConfig {
+                context: context.to_string(),
+                name,
+            });
+        }
+
+        let config = Config {
+            name,
+            sub_command: Some(sub_command.to_string()),
+            ..Default::default()
+        };
+        let overrides = IndexMap::from([(context.to_string(), (None, Some(vec![config])))]);
+        self.merge(CargoRunner(overrides), Origin::Cli)
+    }
+
+    /// Where every setting of the config `name` in `context` came from.
+    ///
+    /// Explains the context's default config when `name` is `None`.
+    pub fn explain(&self, context: Context, name: Option<&str>) -> Option<Explanation> {
+        self.provenance.explain(&self.runner, context, name)
+    }
+}
+
+/// Builds the effective config for a source file by merging, in order, the
+/// built-in configs, the global config, the workspace config, the package
+/// config and the nearest config to the file.
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    global: Option<PathBuf>,
+}
+
+impl Resolver {
+    /// A resolver that uses the default config as the global config.
+    pub fn new() -> Result<Self> {
+        Ok(Resolver {
+            global: Some(CargoRunner::get_default_config_path()?),
+        })
+    }
+
+    /// A resolver with a custom global config, or none at all.
+    pub fn with_global(global: Option<PathBuf>) -> Self {
+        Resolver { global }
+    }
+
+    /// Finds the config files that apply to `file`, lowest precedence first.
+    pub fn layers(&self, file: &Path) -> Result<Vec<Layer>> {
+        let mut layers = vec![Layer::BuiltIn];
+
+        if let Some(global) = self.global.as_ref().filter(|path| path.is_file()) {
+            layers.push(Layer::Global(global.clone()));
+        }
+
+        let start = if file.is_dir() {
+            file.to_path_buf()
+        } else {
+            file.parent().map(Path::to_path_buf).unwrap_or_default()
+        };
+        let start = fs::canonicalize(&start).unwrap_or(start);
+
+        let Some(package_root) = find_ancestor(&start, |dir| dir.join("Cargo.toml").is_file())
+        else {
+            // Outside of a cargo project only the nearest config applies
+            if let Some(local) = nearest_config(&start, None) {
+                layers.push(Layer::Local(local));
+            }
+            return Ok(layers);
+        };
+
+        let workspace_root = find_workspace_root(&package_root)?;
+        if let Some(workspace_root) = workspace_root.filter(|root| *root != package_root) {
+            let config = workspace_root.join(CONFIG_FILE_NAME);
+            if config.is_file() {
+                layers.push(Layer::Workspace(config));
+            }
+        }
+
+        let config = package_root.join(CONFIG_FILE_NAME);
+        if config.is_file() {
+            layers.push(Layer::Package(config));
+        }
+
+        if let Some(local) = nearest_config(&start, Some(&package_root)) {
+            layers.push(Layer::Local(local));
+        }
+
+        Ok(layers)
+    }
+
+    /// Merges every layer that applies to `file` with [`CargoRunner::merge`].
+    ///
+    /// A relative `cwd` is resolved against the directory of the file that sets it.
+    pub fn resolve(&self, file: &Path) -> Result<Resolved> {
+        let layers = self.layers(file)?;
+        // The built-in layer provides the defaults, starting from them would merge them twice
+        let mut resolved = Resolved {
+            runner: CargoRunner(IndexMap::new()),
+            layers: Vec::new(),
+            provenance: Provenance::default(),
+        };
+
+        for layer in layers {
+            let (other, downloaded) = match layer.path() {
+                Some(path) => {
+                    let mut runner = CargoRunner::load(path.to_path_buf())?;
+                    // Provenance is best effort, a broken lockfile must not stop resolving
+                    let lockfile = Lockfile::load(&Lockfile::path(path)).unwrap_or_default();
+                    let downloaded = lockfile
+                        .downloaded(path, &runner)
+                        .into_iter()
+                        .map(|(context, name, preset)| {
+                            (context, name, Origin::Url(preset.source.clone()))
+                        })
+                        .collect();
+                    if let Some(dir) = path.parent() {
+                        anchor_cwd(&mut runner, dir);
+                    }
+                    (runner, downloaded)
+                }
+                None => (CargoRunner::default(), Vec::new()),
+            };
+            resolved.merge_layer(other, layer.origin(), downloaded)?;
+            resolved.layers.push(layer);
+        }
+
+        Ok(resolved)
+    }
+}
+
+impl CargoRunner {
+    /// The effective config for `file`, see [`Resolver`].
+    pub fn resolve(file: &Path) -> Result<Resolved> {
+        Resolver::new()?.resolve(file)
+    }
+}
+
+pub(crate) fn find_ancestor(start: &Path, found: impl Fn(&Path) -> bool) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| found(dir))
+        .map(Path::to_path_buf)
+}
+
+/// The nearest ancestor of `package_root` (itself included) whose `Cargo.toml` has a `[workspace]`.
+pub(crate) fn find_workspace_root(package_root: &Path) -> Result<Option<PathBuf>> {
+    for dir in package_root.ancestors() {
+        let manifest = dir.join("Cargo.toml");
+        if manifest.is_file() && read_manifest(&manifest)?.contains_key("workspace") {
+            return Ok(Some(dir.to_path_buf()));
+        }
+    }
+
+    Ok(None)
+}
+
+pub(crate) fn read_manifest(manifest: &Path) -> Result<toml::Table> {
+    let content =
+        fs::read_to_string(manifest).map_err(|e| Error::io("failed to read", manifest, e))?;
+    toml::from_str(&content).map_err(|e| Error::parse(Some(manifest.to_path_buf()), e))
+}
+
+/// Joins every relative `cwd` in `runner`, hooks included, onto `dir`, except
+/// ones starting with a placeholder.
+fn anchor_cwd(runner: &mut CargoRunner, dir: &Path) {
+    for (_, configs) in runner.0.values_mut() {
+        for config in configs.iter_mut().flatten() {
+            let hooks = config.pre.iter_mut().chain(config.post.iter_mut()).flatten();
+            let hook_cwds = hooks.filter_map(|hook| hook.cwd.as_mut());
+
+            for cwd in config.cwd.iter_mut().chain(hook_cwds) {
+                if !cwd.starts_with('$') && Path::new(cwd.as_str()).is_relative() {
+                    let anchored: PathBuf = dir.join(&*cwd).components().collect();
+                    *cwd = anchored.to_string_lossy().into_owned();
+                }
+            }
+        }
+    }
+}
+
+/// The nearest `cargo-runner.toml` from `start` upwards, stopping before `stop`.
+fn nearest_config(start: &Path, stop: Option<&Path>) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .take_while(|dir| Some(*dir) != stop)
+        .map(|dir| dir.join(CONFIG_FILE_NAME))
+        .find(|config| config.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Context;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn preset(context: &str, name: &str, sub_command: &str) -> String {
+        format!(
+            "[{context}]\ndefault = \"{name}\"\n\n[[{context}.config]]\nname = \"{name}\"\ncommand = \"cargo\"\nsub_command = \"{sub_command}\"\n"
+        )
+    }
+
+    #[test]
+    fn test_resolve_workspace_member() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let global = root.join("home/config.toml");
+        let package = root.join("crates/app");
+        let file = package.join("src/bin/tool/main.rs");
+
+        write(&root.join("Cargo.toml"), "[workspace]\nmembers = [\"crates/*\"]\n");
+        write(&package.join("Cargo.toml"), "[package]\nname = \"app\"\n");
+        write(&file, "fn main() {}\n");
+        write(&global, &preset("run", "global", "run"));
+        write(&root.join(CONFIG_FILE_NAME), &preset("run", "workspace", "run"));
+        write(&package.join(CONFIG_FILE_NAME), &preset("test", "package", "nextest"));
+        write(
+            &package.join("src/bin/tool").join(CONFIG_FILE_NAME),
+            &preset("test", "local", "test"),
+        );
+
+        let resolved = Resolver::with_global(Some(global.clone())).resolve(&file).unwrap();
+
+        assert_eq!(
+            resolved.layers,
+            vec![
+                Layer::BuiltIn,
+                Layer::Global(global),
+                Layer::Workspace(root.join(CONFIG_FILE_NAME)),
+                Layer::Package(package.join(CONFIG_FILE_NAME)),
+                Layer::Local(package.join("src/bin/tool").join(CONFIG_FILE_NAME)),
+            ]
+        );
+        assert_eq!(resolved.files().len(), 4);
+        assert_eq!(resolved.runner.get_default(Context::Run), Some("workspace"));
+        assert_eq!(resolved.runner.get_default(Context::Test), Some("local"));
+        assert!(resolved.runner.find(Context::Run, "global").is_some());
+        assert!(resolved.runner.find(Context::Test, "package").is_some());
+        assert!(resolved.runner.find(Context::Build, "default").is_some());
+
+        let explanation = resolved.explain(Context::Run, None).unwrap();
+        assert_eq!(explanation.name, "workspace");
+        let default = explanation.default.unwrap();
+        assert_eq!(
+            default.effective().unwrap().origin,
+            Origin::File(root.join(CONFIG_FILE_NAME))
+        );
+        assert_eq!(default.shadowed().len(), 2);
+        assert_eq!(
+            explanation.fields["sub_command"].effective().unwrap().origin,
+            Origin::File(root.join(CONFIG_FILE_NAME))
+        );
+    }
+
+    #[test]
+    fn test_resolve_merges_each_layer_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let global = root.join("home/config.toml");
+        let package = root.join("crates/app");
+        let args = |args: &str| format!("[[run.config]]\nname = \"default\"\n{}\n", args);
+
+        write(&root.join("Cargo.toml"), "[workspace]\nmembers = [\"crates/*\"]\n");
+        write(&package.join("Cargo.toml"), "[package]\nname = \"app\"\n");
+        write(&global, &args("args = [\"-v\"]"));
+        write(
+            &root.join(CONFIG_FILE_NAME),
+            &args("args = [\"-v\"]\nextra_args = [\"--nocapture\"]"),
+        );
+        write(
+            &package.join(CONFIG_FILE_NAME),
+            &args("args = [\"--package\"]\nextra_args = [\"--nocapture\"]"),
+        );
+
+        let resolved = Resolver::with_global(Some(global)).resolve(&package).unwrap();
+
+        // Args set by several layers are all passed, each layer's exactly once
+        let config = resolved.runner.find(Context::Run, "default").unwrap();
+        assert_eq!(config.args.as_deref().unwrap(), ["-v", "-v", "--package"]);
+        assert_eq!(config.extra_args.as_deref().unwrap(), ["--nocapture", "--nocapture"]);
+        assert_eq!(config.sub_command.as_deref(), Some("run"));
+
+        let explanation = resolved.explain(Context::Run, None).unwrap();
+        let origins: Vec<_> = explanation.fields["args"].0.iter().map(|a| &a.origin).collect();
+        let files: Vec<_> = resolved.layers[1..].iter().map(Layer::origin).collect();
+        assert_eq!(origins, files.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_resolve_single_package_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let file = root.join("src/main.rs");
+
+        write(&root.join("Cargo.toml"), "[package]\nname = \"app\"\n\n[workspace]\n");
+        write(&file, "fn main() {}\n");
+        write(&root.join(CONFIG_FILE_NAME), &preset("run", "dx", "serve"));
+
+        let resolved = Resolver::with_global(None).resolve(&file).unwrap();
+
+        assert_eq!(
+            resolved.layers,
+            vec![Layer::BuiltIn, Layer::Package(root.join(CONFIG_FILE_NAME))]
+        );
+        assert_eq!(resolved.runner.get_default(Context::Run), Some("dx"));
+    }
+
+    #[test]
+    fn test_resolve_reports_invalid_layer() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let config = root.join(CONFIG_FILE_NAME);
+
+        write(&root.join("Cargo.toml"), "[package]\nname = \"app\"\n");
+        write(&config, "[run\n");
+
+        let err = Resolver::with_global(None).resolve(&root).unwrap_err();
+        assert!(matches!(err, Error::Parse { path: Some(path), .. } if path == config));
+    }
+
+    #[test]
+    fn test_set_sub_command_override() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+
+        write(&root.join("Cargo.toml"), "[package]\nname = \"app\"\n");
+        write(
+            &root.join(CONFIG_FILE_NAME),
+            "[run]\ndefault = \"dx\"\n\n[[run.config]]\nname = \"dx\"\ncommand = \"dx\"\nsub_command = \"serve\"\nallowed_subcommands = [\"serve\", \"build\"]\n",
+        );
+        let mut resolved = Resolver::with_global(None).resolve(&root).unwrap();
+
+        let err = resolved
+            .set_sub_command(Context::Run, None, "bundle")
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::SubCommandNotAllowed { allowed, .. } if allowed == ["serve", "build"])
+        );
+
+        resolved
+            .set_sub_command(Context::Run, None, "build")
+            .unwrap();
+        let explanation = resolved.explain(Context::Run, None).unwrap();
+        let sub_command = explanation.fields["sub_command"].effective().unwrap();
+        assert_eq!(sub_command.value, "\"build\"");
+        assert_eq!(sub_command.origin, Origin::Cli);
+
+        assert!(matches!(
+            resolved.set_sub_command(Context::Run, Some("missing"), "build"),
+            Err(Error::UnknownConfig { .. })
+        ));
+    }
+
+    #[test]
+    fn test_resolve_anchors_cwd() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let package = root.join("crates/app");
+
+        write(&root.join("Cargo.toml"), "[workspace]\nmembers = [\"crates/*\"]\n");
+        write(&package.join("Cargo.toml"), "[package]\nname = \"app\"\n");
+        write(
+            &root.join(CONFIG_FILE_NAME),
+            "[[run.config]]\nname = \"site\"\ncwd = \"./site\"\n\n[[run.config]]\nname = \"css\"\ncwd = \"${packageRoot}/style\"\n",
+        );
+
+        let resolved = Resolver::with_global(None).resolve(&package).unwrap();
+
+        let site = resolved.runner.find(Context::Run, "site").unwrap();
+        assert_eq!(site.cwd.as_deref(), root.join("site").to_str());
+        let css = resolved.runner.find(Context::Run, "css").unwrap();
+        assert_eq!(css.cwd.as_deref(), Some("${packageRoot}/style"));
+    }
+
+    #[test]
+    fn test_explain_downloaded_preset() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let config = root.join(CONFIG_FILE_NAME);
+        let url = "https://example.com/dx.toml";
+
+        write(&root.join("Cargo.toml"), "[package]\nname = \"app\"\n");
+        let fetcher = |_: &str| Ok(preset("run", "dx", "serve"));
+        CargoRunner::download_with_fetcher(url, Some(config.clone()), None, &fetcher).unwrap();
+
+        let resolved = Resolver::with_global(None).resolve(&root).unwrap();
+        // The built-in configs are merged once
+        let built_in = CargoRunner::default();
+        assert_eq!(
+            resolved.runner.find(Context::Build, "default"),
+            built_in.find(Context::Build, "default")
+        );
+        let explanation = resolved.explain(Context::Run, None).unwrap();
+        let sub_command = explanation.fields["sub_command"].effective().unwrap();
+        assert_eq!(sub_command.origin, Origin::Url(url.to_string()));
+        assert_eq!(explanation.fields["sub_command"].shadowed().len(), 0);
+
+        // Once edited, the config is the file's own
+        let mut document = crate::ConfigDocument::load(&config).unwrap();
+        let mut dx = document.runner().unwrap().find(Context::Run, "dx").cloned().unwrap();
+        dx.sub_command = Some("build".to_string());
+        document.add_config(Context::Run, &dx).unwrap();
+        document.save().unwrap();
+
+        let resolved = Resolver::with_global(None).resolve(&root).unwrap();
+        let explanation = resolved.explain(Context::Run, None).unwrap();
+        let sub_command = explanation.fields["sub_command"].effective().unwrap();
+        assert_eq!(sub_command.origin, Origin::File(config));
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{Error, Fetcher, Result};
+
+/// Where [`CargoRunner::download`](crate::CargoRunner::download) gets a config from,
+/// optionally pinned to the sha256 of its content.
+///
+/// Written as one of:
+///
+/// - `https://example.com/leptos.toml`
+/// - `file:///presets/leptos.toml` or a plain path such as `presets/leptos.toml`
+/// - `git+file:///srv/git/presets#leptos/leptos.toml@v1`, read with `git` at `rev`,
+///   `HEAD` when left out. Only a last `@` not followed by a `/` starts the rev, so
+///   paths such as `@scope/leptos.toml` are read at `HEAD`
+///
+/// followed by `#sha256=<hex>`, or `&sha256=<hex>` for git sources, to pin it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    pub location: Location,
+    /// The expected sha256 of the content, in hex.
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    Url(String),
+    File(PathBuf),
+    Git {
+        repo: PathBuf,
+        /// Path of the config inside the repository.
+        path: String,
+        rev: Option<String>,
+    },
+}
+
+/// What [`CargoRunner::download`](crate::CargoRunner::download) fetched and where it went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Downloaded {
+    /// The source pinned to what was fetched: files by absolute path, git sources
+    /// by commit, and [`Downloaded::sha256`] as the checksum.
+    pub source: Source,
+    pub sha256: String,
+    /// The config file the download was merged into.
+    pub path: PathBuf,
+}
+
+impl FromStr for Source {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = |message: &str| Error::InvalidSource {
+            spec: s.to_string(),
+            message: message.to_string(),
+        };
+
+        if let Some(rest) = s.strip_prefix("git+") {
+            let (url, fragment) = rest
+                .split_once('#')
+                .ok_or_else(|| invalid("expected `#path/in/repo.toml` after the repository"))?;
+            let repo = file_url_path(url)
+                .ok_or_else(|| invalid("only `git+file://` repositories are supported"))?;
+
+            let mut parts = fragment.split('&');
+            let target = parts.next().unwrap_or_default();
+            let sha256 = pin(parts).map_err(|message| invalid(&message))?;
+            let (path, rev) = match target.rsplit_once('@') {
+                Some((path, rev)) if !rev.contains('/') => (path, Some(rev.to_string())),
+                _ => (target, None),
+            };
+            if path.is_empty() || rev.as_deref() == Some("") {
+                return Err(invalid("expected `#path/in/repo.toml@rev`"));
+            }
+            // Both end up as arguments to `git`, which would take them for options
+            if path.starts_with('-') || rev.as_deref().is_some_and(|rev| rev.starts_with('-')) {
+                return Err(invalid("the path and rev must not start with `-`"));
+            }
+
+            return Ok(Source {
+                location: Location::Git {
+                    repo,
+                    path: path.to_string(),
+                    rev,
+                },
+                sha256,
+            });
+        }
+
+        let (location, fragment) = match s.split_once('#') {
+            Some((location, fragment)) => (location, Some(fragment)),
+            None => (s, None),
+        };
+        let sha256 = pin(fragment.into_iter().flat_map(|f| f.split('&')))
+            .map_err(|message| invalid(&message))?;
+
+        let location = if location.starts_with("https://") || location.starts_with("http://") {
+            Location::Url(location.to_string())
+        } else if location.starts_with("file://") {
+            let path = file_url_path(location);
+            Location::File(path.ok_or_else(|| invalid("expected `file:///absolute/path`"))?)
+        } else if location.contains("://") {
+            return Err(invalid("expected an http(s) or file URL, a path or a git+file URL"));
+        } else if location.is_empty() {
+            return Err(invalid("expected a URL or a path"));
+        } else {
+            Location::File(PathBuf::from(location))
+        };
+        Ok(Source { location, sha256 })
+    }
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.location {
+            Location::Url(url) => write!(f, "{}", url)?,
+            Location::File(path) if path.is_absolute() => write!(f, "file://{}", path.display())?,
+            Location::File(path) => write!(f, "{}", path.display())?,
+            Location::Git { repo, path, rev } => {
+                write!(f, "git+file://{}#{}", repo.display(), path)?;
+                if let Some(rev) = rev {
+                    write!(f, "@{}", rev)?;
+                }
+            }
+        }
+        match (&self.sha256, &self.location) {
+            (Some(sha256), Location::Git { .. }) => write!(f, "&sha256={}", sha256),
+            (Some(sha256), _) => write!(f, "#sha256={}", sha256),
+            (None, _) => Ok(()),
+        }
+    }
+}
+
+impl Source {
+    /// Reads the content of the source, fetching URLs with `fetcher`.
+    ///
+    /// Also returns the location pinned to what was read, see [`Downloaded::source`].
+    pub(crate) fn read(&self, fetcher: &dyn Fetcher) -> Result<(String, Location)> {
+        match &self.location {
+            Location::Url(url) => Ok((fetcher.fetch(url)?, self.location.clone())),
+            _ => self.read_local(),
+        }
+    }
+
+    /// [`Source::read`] for file and git sources, URLs fail without a [`Fetcher`].
+    pub(crate) fn read_local(&self) -> Result<(String, Location)> {
+        match &self.location {
+            Location::Url(url) => Err(Error::network(url, "no fetcher for URLs")),
+            Location::File(path) => {
+                let failed = |e| Error::io("failed to read", path, e);
+                let content = fs::read_to_string(path).map_err(failed)?;
+                let path = fs::canonicalize(path).map_err(failed)?;
+                Ok((content, Location::File(path)))
+            }
+            Location::Git { repo, path, rev } => {
+                let rev = format!("{}^{{commit}}", rev.as_deref().unwrap_or("HEAD"));
+                let commit = self.git(repo, &["rev-parse", "--verify", "--end-of-options", &rev])?;
+                let commit = commit.trim().to_string();
+                let content = self.git(repo, &["show", &format!("{}:{}", commit, path)])?;
+                let location = Location::Git {
+                    repo: repo.clone(),
+                    path: path.clone(),
+                    rev: Some(commit),
+                };
+                Ok((content, location))
+            }
+        }
+    }
+
+    /// Fails unless `content` matches the pinned sha256, returning its digest.
+    pub(crate) fn verify(&self, content: &str) -> Result<String> {
+        let digest = sha256(content.as_bytes());
+        match &self.sha256 {
+            Some(expected) if !expected.eq_ignore_ascii_case(&digest) => {
+                Err(Error::ChecksumMismatch {
+                    spec: self.to_string(),
+                    expected: expected.clone(),
+                    actual: digest,
+                })
+            }
+            _ => Ok(digest),
+        }
+    }
+
+    fn git(&self, repo: &Path, args: &[&str]) -> Result<String> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args(args)
+            .output()
+            .map_err(|e| self.git_error(format!("failed to run git: {}", e)))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(self.git_error(stderr.trim()));
+        }
+        String::from_utf8(output.stdout).map_err(|e| self.git_error(e))
+    }
+
+    fn git_error(&self, message: impl Display) -> Error {
+        Error::Git {
+            spec: self.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// The hex sha256 of `content`.
+pub(crate) fn sha256(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// `/srv/presets` for `file:///srv/presets` and `file://localhost/srv/presets`.
+fn file_url_path(url: &str) -> Option<PathBuf> {
+    let path = url.strip_prefix("file://")?;
+    let path = path.strip_prefix("localhost").unwrap_or(path);
+    path.starts_with('/').then(|| PathBuf::from(path))
+}
+
+fn pin<'a>(parts: impl Iterator<Item = &'a str>) -> Result<Option<String>, String> {
+    let mut sha256 = None;
+    for part in parts {
+        match part.strip_prefix("sha256=") {
+            Some(hex) if hex.len() == 64 && hex.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                sha256 = Some(hex.to_ascii_lowercase())
+            }
+            Some(_) => return Err("`sha256=` expects 64 hex digits".to_string()),
+            None => return Err(format!("unknown `{}`, expected `sha256=<hex>`", part)),
+        }
+    }
+    Ok(sha256)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn test_parse_and_display_sources() {
+        let cases = [
+            "https://example.com/leptos.toml",
+            "file:///srv/presets/leptos.toml",
+            "presets/leptos.toml",
+            "git+file:///srv/git/presets#leptos/leptos.toml",
+            "git+file:///srv/git/presets#leptos/leptos.toml@v1",
+            "git+file:///srv/git/presets#presets/@scope/leptos.toml",
+            "git+file:///srv/git/presets#presets/@scope/leptos.toml@v1",
+        ];
+        for case in cases {
+            let source: Source = case.parse().unwrap();
+            assert_eq!(source.to_string(), case);
+            assert_eq!(source.sha256, None);
+        }
+
+        let source: Source = format!("git+file:///srv/git/presets#dx.toml@main&sha256={}", DIGEST)
+            .parse()
+            .unwrap();
+        assert_eq!(
+            source.location,
+            Location::Git {
+                repo: PathBuf::from("/srv/git/presets"),
+                path: "dx.toml".to_string(),
+                rev: Some("main".to_string()),
+            }
+        );
+        assert_eq!(source.sha256.as_deref(), Some(DIGEST));
+        let source: Source =
+            "git+file:///srv/git/presets#presets/@scope/leptos.toml".parse().unwrap();
+        assert!(matches!(
+            source.location,
+            Location::Git { path, rev: None, .. } if path == "presets/@scope/leptos.toml"
+        ));
+        let source: Source =
+            format!("leptos.toml#sha256={}", DIGEST.to_uppercase()).parse().unwrap();
+        assert_eq!(source.to_string(), format!("leptos.toml#sha256={}", DIGEST));
+
+        for invalid in [
+            "git+https://example.com/presets#leptos.toml",
+            "git+file:///srv/git/presets",
+            "ssh://example.com/leptos.toml",
+            "leptos.toml#sha256=abc",
+            "git+file:///srv/git/presets#leptos.toml@--output=x",
+            "git+file:///srv/git/presets#--output=/tmp/x",
+        ] {
+            assert!(
+                matches!(invalid.parse::<Source>(), Err(Error::InvalidSource { .. })),
+                "{invalid} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn test_verify_sha256() {
+        let source: Source = format!("leptos.toml#sha256={}", DIGEST).parse().unwrap();
+        assert_eq!(source.verify("hello").unwrap(), DIGEST);
+
+        let err = source.verify("tampered").unwrap_err();
+        assert!(matches!(err, Error::ChecksumMismatch { .. }));
+        assert!(err.to_string().starts_with(&format!(
+            "checksum mismatch for leptos.toml#sha256={}: expected sha256 {}, got ",
+            DIGEST, DIGEST
+        )));
+    }
+
+    #[test]
+    fn test_git_failures_are_git_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let source: Source = format!("git+file://{}#dx.toml", dir.path().display())
+            .parse()
+            .unwrap();
+        let err = source.read_local().unwrap_err();
+        assert!(matches!(err, Error::Git { .. }), "{err}");
+
+        // A rev that looks like an option is still only ever a rev
+        let status = Command::new("git").arg("init").arg("-q").arg(dir.path()).status();
+        assert!(status.unwrap().success());
+        let output = dir.path().join("output");
+        let source = Source {
+            location: Location::Git {
+                repo: dir.path().to_path_buf(),
+                path: "dx.toml".to_string(),
+                rev: Some(format!("--output={}", output.display())),
+            },
+            sha256: None,
+        };
+        let err = source.read_local().unwrap_err();
+        assert!(matches!(err, Error::Git { .. }), "{err}");
+        assert!(!output.exists());
+    }
+}
@@ -0,0 +1,509 @@
+use std::{collections::HashMap, fs, ops::Range, path::Path};
+
+use toml_edit::{ImDocument, Item, Key, TableLike};
+
+use crate::{migration::RENAMED_KEYS, CargoRunner, CommandType, Diagnostic, Error, ParseMode, Result, Span};
+
+/// Keys accepted inside a context table such as `[run]`.
+const CONTEXT_KEYS: &[&str] = &["default", "config"];
+
+/// Keys accepted inside a `[[$context.config]]` entry, kept in sync with [`crate::Config`].
+const CONFIG_KEYS: &[&str] = &[
+    "name",
+    "command_type",
+    "command",
+    "sub_command",
+    "toolchain",
+    "profile",
+    "target",
+    "features",
+    "all_features",
+    "no_default_features",
+    "args",
+    "extra_args",
+    "allowed_subcommands",
+    "cwd",
+    "clear_env",
+    "env_remove",
+    "path_prepend",
+    "pre",
+    "post",
+    "env",
+];
+
+const HOOK_KEYS: &[&str] = &["name", "command", "args", "cwd", "on_failure", "env"];
+
+impl CargoRunner {
+    /// Checks the config file at `path` and returns every problem found in it.
+    ///
+    /// Only failing to read the file is an [`Error`]; everything wrong with its
+    /// contents, including TOML syntax errors, is reported as a [`Diagnostic`].
+    pub fn validate(path: &Path) -> Result<Vec<Diagnostic>> {
+        Self::validate_with(path, ParseMode::Strict)
+    }
+
+    /// Same as [`CargoRunner::validate`], reporting values that [`ParseMode::Lenient`]
+    /// tolerates as warnings instead of errors.
+    pub fn validate_with(path: &Path, mode: ParseMode) -> Result<Vec<Diagnostic>> {
+        Ok(Self::validate_str_with(&read(path)?, mode))
+    }
+
+    /// Same as [`CargoRunner::validate`] for a config that is already in memory.
+    pub fn validate_str(source: &str) -> Vec<Diagnostic> {
+        Self::validate_str_with(source, ParseMode::Strict)
+    }
+
+    pub fn validate_str_with(source: &str, mode: ParseMode) -> Vec<Diagnostic> {
+        Validator::new(source, mode).run().diagnostics
+    }
+
+    /// Parses a config, replacing an unknown `command_type` with `cargo`
+    /// and returning a warning for each value that was replaced.
+    pub fn from_str_lenient(source: &str) -> Result<(CargoRunner, Vec<Diagnostic>)> {
+        let validator = Validator::new(source, ParseMode::Lenient).run();
+        let runner = validator.patched().parse()?;
+        let warnings = validator
+            .diagnostics
+            .into_iter()
+            .filter(|d| {
+                d.span.as_ref().is_some_and(|span| {
+                    validator.unknown_command_types.contains(&span.range)
+                })
+            })
+            .collect();
+
+        Ok((runner, warnings))
+    }
+
+    /// Same as [`CargoRunner::from_str_lenient`] for the config at `path`.
+    pub fn load_lenient(path: &Path) -> Result<(CargoRunner, Vec<Diagnostic>)> {
+        Self::from_str_lenient(&read(path)?).map_err(|e| e.with_path(path))
+    }
+}
+
+fn read(path: &Path) -> Result<String> {
+    fs::read_to_string(path).map_err(|e| Error::io("failed to read", path, e))
+}
+
+struct Validator<'a> {
+    source: &'a str,
+    mode: ParseMode,
+    diagnostics: Vec<Diagnostic>,
+    /// Spans of `command_type` values that were downgraded to warnings in lenient mode.
+    unknown_command_types: Vec<Range<usize>>,
+}
+
+impl<'a> Validator<'a> {
+    fn new(source: &'a str, mode: ParseMode) -> Self {
+        Validator {
+            source,
+            mode,
+            diagnostics: Vec::new(),
+            unknown_command_types: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> Self {
+        self.check();
+        self.diagnostics
+            .sort_by_key(|d| d.span.as_ref().map(|span| span.range.start));
+        self
+    }
+
+    /// The source with every tolerated `command_type` replaced by `cargo`.
+    fn patched(&self) -> String {
+        let mut patched = self.source.to_string();
+        for range in self.unknown_command_types.iter().rev() {
+            patched.replace_range(range.clone(), "\"cargo\"");
+        }
+        patched
+    }
+
+    fn check(&mut self) {
+        let document = match ImDocument::parse(self.source) {
+            Ok(document) => document,
+            Err(e) => {
+                let diagnostic = Diagnostic::error(e.message().trim_end());
+                self.push(diagnostic, e.span());
+                return;
+            }
+        };
+
+        let root = document.as_table();
+        for (context, item) in root.iter() {
+            // Checked by the deserializer below
+            if context == "version" {
+                continue;
+            }
+
+            match item.as_table_like() {
+                Some(table) => self.context(context, table),
+                None => self.push(
+                    Diagnostic::error(format!(
+                        "`{}` must be a table with `default` and `config`",
+                        context
+                    )),
+                    key_span(root.key(context)),
+                ),
+            }
+        }
+
+        // Anything the checks above do not know about, such as wrong value types,
+        // still surfaces through the same deserializer that `load` uses.
+        if !self.diagnostics.iter().any(Diagnostic::is_error) {
+            if let Err(e) = toml::from_str::<CargoRunner>(&self.patched()) {
+                self.push(Diagnostic::error(e.message()), e.span());
+            }
+        }
+    }
+
+    fn context(&mut self, context: &str, table: &dyn TableLike) {
+        self.unknown_keys(table, CONTEXT_KEYS, &format!("context `{}`", context));
+
+        let mut names: HashMap<&str, Option<Range<usize>>> = HashMap::new();
+
+        for config in configs(table.get("config")) {
+            let Some(name) = config.get("name") else {
+                self.push(
+                    Diagnostic::error(format!("config in `{}` is missing a `name`", context)),
+                    config_span(config),
+                );
+                continue;
+            };
+            let name_span = name.span();
+
+            if let Some(name) = name.as_str() {
+                if let Some(first) = names.get(name) {
+                    let mut diagnostic = Diagnostic::error(format!(
+                        "duplicate config `{}` in `{}`",
+                        name, context
+                    ));
+                    if let Some(first) = first {
+                        let line = Span::new(self.source, first.clone()).start.line;
+                        diagnostic = diagnostic
+                            .with_suggestion(format!("first defined on line {}", line));
+                    }
+                    self.push(diagnostic, name_span);
+                } else {
+                    names.insert(name, name_span);
+                }
+            }
+
+            self.config(config);
+        }
+
+        if let Some(default) = table.get("default") {
+            let Some(name) = default.as_str() else {
+                return;
+            };
+            if !names.contains_key(name) {
+                let mut diagnostic = Diagnostic::error(format!(
+                    "default `{}` does not name a config in `{}`",
+                    name, context
+                ));
+                let candidates: Vec<&str> = names.keys().copied().collect();
+                if let Some(closest) = closest(name, &candidates) {
+                    diagnostic = diagnostic.with_suggestion(format!("did you mean `{}`?", closest));
+                } else if !candidates.is_empty() {
+                    let mut candidates = candidates;
+                    candidates.sort();
+                    diagnostic = diagnostic
+                        .with_suggestion(format!("available: {}", candidates.join(", ")));
+                }
+                self.push(diagnostic, default.span());
+            }
+        }
+    }
+
+    fn config(&mut self, config: &dyn TableLike) {
+        self.unknown_keys(config, CONFIG_KEYS, "config");
+        for field in ["pre", "post"] {
+            for hook in configs(config.get(field)) {
+                self.unknown_keys(hook, HOOK_KEYS, &format!("`{}` hook", field));
+            }
+        }
+
+        if let Some(command) = config.get("command") {
+            if command.as_str().is_some_and(|c| c.trim().is_empty()) {
+                self.push(Diagnostic::error("`command` must not be empty"), command.span());
+            }
+        }
+
+        if let Some(command_type) = config.get("command_type") {
+            if let Some(value) = command_type.as_str() {
+                if let Err(e) = value.parse::<CommandType>() {
+                    let mut diagnostic = match self.mode {
+                        ParseMode::Strict => Diagnostic::error(e.to_string()),
+                        ParseMode::Lenient => {
+                            if let Some(span) = command_type.span() {
+                                self.unknown_command_types.push(span);
+                            }
+                            Diagnostic::warning(format!("{}, falling back to `cargo`", e))
+                        }
+                    };
+                    if let Some(closest) = closest(value, CommandType::VARIANTS) {
+                        diagnostic =
+                            diagnostic.with_suggestion(format!("did you mean `{}`?", closest));
+                    }
+                    self.push(diagnostic, command_type.span());
+                }
+            }
+        }
+
+        let allowed: Vec<&str> = config
+            .get("allowed_subcommands")
+            .and_then(Item::as_array)
+            .map(|allowed| allowed.iter().filter_map(|v| v.as_str()).collect())
+            .unwrap_or_default();
+        let sub_command = config.get("sub_command");
+        if let Some(value) = sub_command.and_then(Item::as_str) {
+            if !allowed.is_empty() && !allowed.contains(&value) {
+                let name = config.get("name").and_then(Item::as_str).unwrap_or_default();
+                let error = Error::SubCommandNotAllowed {
+                    name: name.to_string(),
+                    sub_command: value.to_string(),
+                    allowed: allowed.iter().map(|a| a.to_string()).collect(),
+                };
+                let mut diagnostic = Diagnostic::error(error.to_string());
+                if let Some(closest) = closest(value, &allowed) {
+                    diagnostic = diagnostic.with_suggestion(format!("did you mean `{}`?", closest));
+                }
+                self.push(diagnostic, sub_command.and_then(Item::span));
+            }
+        }
+    }
+
+    fn unknown_keys(&mut self, table: &dyn TableLike, known: &[&str], owner: &str) {
+        for (key, _) in table.iter() {
+            if known.contains(&key) {
+                continue;
+            }
+            let mut diagnostic =
+                Diagnostic::warning(format!("unknown key `{}` in {}", key, owner));
+            if let Some(closest) = closest(key, known) {
+                diagnostic = diagnostic.with_suggestion(format!("did you mean `{}`?", closest));
+            } else if let Some((_, renamed)) = RENAMED_KEYS.iter().find(|(old, _)| *old == key) {
+                diagnostic = diagnostic.with_suggestion(format!(
+                    "renamed to `{}`, run `rx migrate` to upgrade the file",
+                    renamed
+                ));
+            }
+            self.push(diagnostic, key_span(table.key(key)));
+        }
+    }
+
+    fn push(&mut self, diagnostic: Diagnostic, span: Option<Range<usize>>) {
+        let span = span.map(|range| Span::new(self.source, range));
+        self.diagnostics.push(diagnostic.with_span(span));
+    }
+}
+
+/// Every config entry of a context, whether written as `[[run.config]]` or `config = [{ .. }]`.
+///
+/// Also used for hooks, which are written the same way.
+fn configs(item: Option<&Item>) -> Vec<&dyn TableLike> {
+    match item {
+        Some(Item::ArrayOfTables(tables)) => {
+            tables.iter().map(|table| table as &dyn TableLike).collect()
+        }
+        Some(Item::Value(value)) => value
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|value| value.as_inline_table())
+            .map(|table| table as &dyn TableLike)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn config_span(config: &dyn TableLike) -> Option<Range<usize>> {
+    config
+        .iter()
+        .next()
+        .and_then(|(key, _)| key_span(config.key(key)))
+}
+
+fn key_span(key: Option<&Key>) -> Option<Range<usize>> {
+    key.and_then(Key::span)
+}
+
+/// The candidate closest to `name`, if it is close enough to be a likely typo.
+fn closest<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    candidates
+        .iter()
+        .map(|candidate| (levenshtein(name, candidate), *candidate))
+        .filter(|(distance, candidate)| *distance <= (candidate.len() / 3).max(2))
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous + usize::from(ca != *cb);
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Severity;
+
+    fn messages(source: &str) -> Vec<String> {
+        CargoRunner::validate_str(source)
+            .into_iter()
+            .map(|d| d.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_default_config_is_valid() {
+        let source = String::try_from(CargoRunner::default()).unwrap();
+        assert_eq!(CargoRunner::validate_str(&source), vec![]);
+    }
+
+    #[test]
+    fn test_unknown_key_with_suggestion() {
+        let source = "[run]\ndefault = \"leptos\"\n\n[[run.config]]\nname = \"leptos\"\nallowed_subconfig = []\n";
+
+        let diagnostics = CargoRunner::validate_str(source);
+
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.severity, Severity::Warning);
+        assert_eq!(
+            diagnostic.suggestion.as_deref(),
+            Some("did you mean `allowed_subcommands`?")
+        );
+        let span = diagnostic.span.as_ref().unwrap();
+        assert_eq!((span.start.line, span.start.column), (6, 1));
+        assert_eq!(&source[span.range.clone()], "allowed_subconfig");
+    }
+
+    #[test]
+    fn test_renamed_key_suggests_migration() {
+        assert_eq!(
+            messages("[[run.commands]]\nname = \"leptos\"\n"),
+            vec!["1:7: warning: unknown key `commands` in context `run` (renamed to `config`, run `rx migrate` to upgrade the file)"]
+        );
+    }
+
+    #[test]
+    fn test_semantic_errors() {
+        let source = r#"[run]
+default = "leptoss"
+
+[[run.config]]
+name = "leptos"
+command = ""
+
+[[run.config]]
+name = "leptos"
+command_type = "shel"
+
+[[run.config]]
+name = "dx"
+command = "dx"
+sub_command = "serv"
+allowed_subcommands = ["serve", "build"]
+"#;
+
+        assert_eq!(
+            messages(source),
+            vec![
+                "2:11: error: default `leptoss` does not name a config in `run` (did you mean `leptos`?)",
+                "6:11: error: `command` must not be empty",
+                "9:8: error: duplicate config `leptos` in `run` (first defined on line 5)",
+                "10:16: error: unknown command_type `shel`, expected one of `cargo`, `subcommand`, `shell` (did you mean `shell`?)",
+                "15:15: error: sub_command `serv` is not allowed for config 'dx', expected one of `serve`, `build` (did you mean `serve`?)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_syntax_and_type_errors() {
+        assert_eq!(
+            messages("[run\n"),
+            vec!["1:5: error: invalid table header\nexpected `.`, `]`"]
+        );
+        assert_eq!(
+            messages("[[run.config]]\nname = 1\n"),
+            vec!["2:8: error: invalid type: integer `1`, expected a string"]
+        );
+    }
+
+    #[test]
+    fn test_schema_matches_known_keys() {
+        let schema = CargoRunner::json_schema();
+        let definitions = &schema["definitions"];
+        let keys = |definition: &str| {
+            let mut keys: Vec<_> = definitions[definition]["properties"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect();
+            keys.sort();
+            keys
+        };
+        let sorted = |known: &[&str]| {
+            let mut known: Vec<_> = known.iter().map(|k| k.to_string()).collect();
+            known.sort();
+            known
+        };
+
+        assert_eq!(keys("Config"), sorted(CONFIG_KEYS));
+        assert_eq!(keys("Context"), sorted(CONTEXT_KEYS));
+        assert_eq!(keys("Hook"), sorted(HOOK_KEYS));
+        let variants: Vec<_> = definitions["CommandType"]["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|variant| variant["const"].as_str().unwrap())
+            .collect();
+        let aliases = CommandType::ALIASES.iter().map(|(alias, _)| *alias);
+        let accepted: Vec<_> = CommandType::VARIANTS.iter().copied().chain(aliases).collect();
+        assert_eq!(variants, accepted);
+        // Every value the schema offers loads, and no other spelling does
+        for variant in &variants {
+            assert!(variant.parse::<CommandType>().is_ok(), "{variant} should load");
+        }
+        for rejected in ["Cargo", "sub command", "subCommand", "bin"] {
+            assert!(rejected.parse::<CommandType>().is_err(), "{rejected} should not load");
+        }
+    }
+
+    #[test]
+    fn test_lenient_mode_downgrades_command_type() {
+        let source = "[[run.config]]\nname = \"dx\"\ncommand_type = \"shel\"\ncommand = \"dx\"\n";
+
+        assert!(source.parse::<CargoRunner>().is_err());
+        assert_eq!(
+            messages(source),
+            vec!["3:16: error: unknown command_type `shel`, expected one of `cargo`, `subcommand`, `shell` (did you mean `shell`?)"]
+        );
+
+        let (runner, warnings) = CargoRunner::from_str_lenient(source).unwrap();
+        assert_eq!(
+            runner.find(crate::Context::Run, "dx").unwrap().command_type,
+            Some(CommandType::Cargo)
+        );
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].severity, Severity::Warning);
+        assert_eq!(
+            CargoRunner::validate_str_with(source, ParseMode::Lenient),
+            warnings
+        );
+    }
+}
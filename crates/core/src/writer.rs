@@ -0,0 +1,160 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{Error, Result};
+
+/// The content of a file when it was read, to tell whether someone else wrote
+/// it since.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FileVersion(Option<u64>);
+
+impl FileVersion {
+    /// The file did not exist.
+    pub(crate) const MISSING: FileVersion = FileVersion(None);
+
+    pub(crate) fn of(content: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        FileVersion(Some(hasher.finish()))
+    }
+
+    /// The version of `path` as it is now, [`FileVersion::MISSING`] if there is none.
+    pub(crate) fn current(path: &Path) -> Result<Self> {
+        match fs::read(path) {
+            Ok(content) => Ok(FileVersion::of(&content)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(FileVersion::MISSING),
+            Err(e) => Err(Error::io("failed to read", path, e)),
+        }
+    }
+}
+
+/// Reads `path` along with the version to pass back when writing it.
+pub(crate) fn read(path: &Path) -> Result<(String, FileVersion)> {
+    let content = fs::read_to_string(path).map_err(|e| Error::io("failed to read", path, e))?;
+    let version = FileVersion::of(content.as_bytes());
+    Ok((content, version))
+}
+
+/// Replaces `path` with `content`, see [`FileLock::write`].
+pub(crate) fn write(
+    path: &Path,
+    content: &[u8],
+    expected: Option<FileVersion>,
+) -> Result<FileVersion> {
+    FileLock::acquire(path)?.write(content, expected)
+}
+
+/// An advisory lock on a file, held on `<file>.lock` next to it until dropped.
+///
+/// The lock is only honored by other writers going through this module, and it
+/// is not reentrant: a process taking it twice for the same file waits forever.
+pub(crate) struct FileLock {
+    path: PathBuf,
+    _lock: File,
+}
+
+impl FileLock {
+    /// Blocks until no other process holds the lock of `path`.
+    pub(crate) fn acquire(path: &Path) -> Result<FileLock> {
+        // Write through symlinks, e.g. a config kept in a dotfiles repository
+        let path = match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                fs::canonicalize(path).map_err(|e| Error::io("failed to resolve", path, e))?
+            }
+            _ => path.to_path_buf(),
+        };
+
+        let mut lock_name = path.file_name().unwrap_or_default().to_os_string();
+        lock_name.push(".lock");
+        let lock_path = path.with_file_name(lock_name);
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .map_err(|e| Error::io("failed to lock", &lock_path, e))?;
+        lock.lock().map_err(|e| Error::io("failed to lock", &lock_path, e))?;
+
+        Ok(FileLock { path, _lock: lock })
+    }
+
+    /// Atomically replaces the locked file with `content`.
+    ///
+    /// Fails with [`Error::Modified`] when the file no longer matches `expected`,
+    /// the version it had when it was read; `None` overwrites whatever is there.
+    pub(crate) fn write(
+        &self,
+        content: &[u8],
+        expected: Option<FileVersion>,
+    ) -> Result<FileVersion> {
+        if let Some(expected) = expected {
+            if FileVersion::current(&self.path)? != expected {
+                return Err(Error::Modified(self.path.clone()));
+            }
+        }
+        replace(&self.path, content)?;
+        Ok(FileVersion::of(content))
+    }
+}
+
+/// Writes `content` to a temporary file next to `path`, syncs it to disk and
+/// renames it over `path`, so readers see either the old or the new file.
+///
+/// Takes no lock, callers hold the [`FileLock`] of the file this is written for.
+pub(crate) fn replace(path: &Path, content: &[u8]) -> Result<()> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let failed = |e| Error::io("failed to write", path, e);
+
+    let mut temp = tempfile::NamedTempFile::new_in(dir).map_err(failed)?;
+    temp.write_all(content).map_err(failed)?;
+    // Temporary files are only readable by their owner, keep the file's permissions
+    if let Ok(metadata) = fs::metadata(path) {
+        fs::set_permissions(temp.path(), metadata.permissions()).map_err(failed)?;
+    }
+    temp.as_file().sync_all().map_err(failed)?;
+    temp.persist(path).map_err(|e| failed(e.error))?;
+
+    // Make the rename itself durable; not every platform can sync a directory
+    #[cfg(unix)]
+    let _ = File::open(dir).and_then(|dir| dir.sync_all());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_detects_concurrent_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+
+        let version = write(&path, b"[run]\n", Some(FileVersion::MISSING)).unwrap();
+        let (content, read_version) = read(&path).unwrap();
+        assert_eq!((content.as_str(), read_version), ("[run]\n", version));
+
+        // Someone else writes the file after it was read
+        fs::write(&path, "[build]\n").unwrap();
+        let err = write(&path, b"[test]\n", Some(version)).unwrap_err();
+        assert!(matches!(err, Error::Modified(_)));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[build]\n");
+
+        write(&path, b"[test]\n", None).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[test]\n");
+
+        // Only the file and its lock are left, no temporary files
+        let mut names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["config.toml", "config.toml.lock"]);
+    }
+}